@@ -14,14 +14,29 @@ type CompressedExpr = Vec<(CID, S)>;
 type CompressedConstraint = (CompressedExpr, CompressedExpr, CompressedExpr); // A, B, C
 
 pub type ConstraintID = usize;
+
+// Open transaction: number of constraints when it began and start of its undo log
+struct Transaction {
+    no_constraints: usize,
+    undo_start: usize,
+}
+
 pub struct ConstraintStorage {
     field_tracker: FieldTracker,
     constraints: Vec<(CompressedConstraint, usize)>,
+    // previous versions of the constraints replaced inside the open transactions
+    undo_log: Vec<(ConstraintID, CompressedConstraint)>,
+    transactions: Vec<Transaction>,
 }
 
 impl ConstraintStorage {
     pub fn new() -> ConstraintStorage {
-        ConstraintStorage { field_tracker: FieldTracker::new(), constraints: Vec::new()}
+        ConstraintStorage {
+            field_tracker: FieldTracker::new(),
+            constraints: Vec::new(),
+            undo_log: Vec::new(),
+            transactions: Vec::new(),
+        }
     }
 
     pub fn add_constraint(&mut self, constraint: C) -> ConstraintID {
@@ -56,13 +71,49 @@ impl ConstraintStorage {
 
     pub fn replace(&mut self, id: ConstraintID, new: C) {
         if id < self.constraints.len() {
-            self.constraints[id].0 = logic::code_constraint(new, &mut self.field_tracker);
+            let compressed = logic::code_constraint(new, &mut self.field_tracker);
+            let old = std::mem::replace(&mut self.constraints[id].0, compressed);
+            if !self.transactions.is_empty() {
+                self.undo_log.push((id, old));
+            }
+        }
+    }
+
+    // Transactions can be nested, the changes of a committed transaction are undone
+    // if the enclosing one is rolled back
+    pub fn begin_transaction(&mut self) {
+        self.transactions.push(Transaction {
+            no_constraints: self.constraints.len(),
+            undo_start: self.undo_log.len(),
+        });
+    }
+
+    pub fn commit_transaction(&mut self) {
+        self.transactions.pop().expect("there is no transaction to commit");
+        if self.transactions.is_empty() {
+            self.undo_log.clear();
+        }
+    }
+
+    // Restores the replaced constraints and removes the added ones
+    pub fn rollback_transaction(&mut self) {
+        let transaction = self.transactions.pop().expect("there is no transaction to roll back");
+        while self.undo_log.len() > transaction.undo_start {
+            let (id, old) = self.undo_log.pop().unwrap();
+            self.constraints[id].0 = old;
         }
+        self.constraints.truncate(transaction.no_constraints);
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        !self.transactions.is_empty()
     }
 
 
 
     pub fn extract_with(&mut self, filter: &dyn Fn(&C) -> bool) -> LinkedList<C> {
+        // the ids change, so the undo log would not be valid
+        debug_assert!(self.transactions.is_empty());
         let old = std::mem::take(&mut self.constraints);
         let mut removed = LinkedList::new();
         for c in old {
@@ -88,3 +139,36 @@ impl ConstraintStorage {
         self.constraints.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn linear(coefficients: &[(usize, i64)]) -> C {
+        let mut c = HashMap::new();
+        for (signal, value) in coefficients {
+            c.insert(*signal, BigInt::from(*value));
+        }
+        C::new(HashMap::new(), HashMap::new(), c)
+    }
+
+    #[test]
+    fn rollback_restores_replaced_and_added_constraints() {
+        let mut storage = ConstraintStorage::new();
+        let first = storage.add_constraint(linear(&[(1, 1), (2, 3)]));
+        storage.begin_transaction();
+        storage.replace(first, linear(&[(1, 5)]));
+        storage.begin_transaction();
+        storage.replace(first, C::empty());
+        storage.add_constraint(linear(&[(3, 1)]));
+        storage.commit_transaction();
+        assert_eq!(storage.get_no_constraints(), 2);
+        storage.rollback_transaction();
+        assert!(!storage.in_transaction());
+        assert_eq!(storage.get_no_constraints(), 1);
+        let restored = storage.read_constraint(first).unwrap();
+        assert_eq!(restored.c().len(), 2);
+        assert_eq!(restored.c().get(&2), Some(&BigInt::from(3)));
+    }
+}
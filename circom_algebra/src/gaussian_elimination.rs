@@ -0,0 +1,286 @@
+use super::modular_arithmetic;
+use super::num_bigint::BigInt;
+use crate::num_traits::Zero;
+use crate::simplification_utils::{Config, Simplified};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList};
+
+type C = crate::algebra::Constraint<usize>;
+type Row = HashMap<usize, BigInt>;
+
+/*
+    Sparse Gauss-Jordan elimination over F_p for a cluster of linear constraints.
+    Pivots are chosen with a minimum-degree heuristic: the shortest active row is
+    taken first and, inside it, the eliminable signal that appears in the fewest
+    rows (ties go to the greatest signal, as in take_signal). Forbidden signals are
+    never used as pivots of a substitution; rows that only contain forbidden signals
+    are reduced among themselves and kept as constraints.
+*/
+
+pub struct EliminationReport {
+    // rank of the coefficient matrix of the cluster
+    pub rank: usize,
+    // positions (in the input list) of the constraints that were linearly dependent
+    pub redundant: LinkedList<usize>,
+    // positions of the constraints that were reduced to 0 = k with k != 0
+    pub inconsistent: LinkedList<usize>,
+    // signals of the cluster that are not determined by the rest of the cluster
+    pub free_signals: LinkedList<usize>,
+}
+
+struct SparseSystem<'a> {
+    field: &'a BigInt,
+    rows: Vec<Row>,
+    // signal -> rows in which the signal has a non zero coefficient
+    columns: HashMap<usize, BTreeSet<usize>>,
+    pivots: Vec<Option<usize>>,
+}
+
+impl<'a> SparseSystem<'a> {
+    fn new(constraints: LinkedList<C>, field: &'a BigInt) -> SparseSystem<'a> {
+        let constant = C::constant_coefficient();
+        let mut rows = Vec::with_capacity(constraints.len());
+        let mut columns: HashMap<usize, BTreeSet<usize>> = HashMap::new();
+        for constraint in constraints {
+            debug_assert!(C::is_linear(&constraint));
+            let id = rows.len();
+            let mut row = Row::new();
+            for (signal, value) in constraint.c() {
+                let value = modular_arithmetic::add(value, &BigInt::zero(), field);
+                if value.is_zero() {
+                    continue;
+                }
+                if *signal != constant {
+                    columns.entry(*signal).or_default().insert(id);
+                }
+                row.insert(*signal, value);
+            }
+            rows.push(row);
+        }
+        let pivots = vec![None; rows.len()];
+        SparseSystem { field, rows, columns, pivots }
+    }
+
+    fn no_signals(&self, row: usize) -> usize {
+        let constant = C::constant_coefficient();
+        let len = self.rows[row].len();
+        if self.rows[row].contains_key(&constant) {
+            len - 1
+        } else {
+            len
+        }
+    }
+
+    fn choose_pivot(&self, row: usize, can_be_taken: &dyn Fn(usize) -> bool) -> Option<usize> {
+        let constant = C::constant_coefficient();
+        let mut best: Option<(usize, usize)> = None;
+        for signal in self.rows[row].keys() {
+            if *signal == constant || !can_be_taken(*signal) {
+                continue;
+            }
+            let degree = self.columns.get(signal).map_or(0, |rows| rows.len());
+            best = match best {
+                Some((b_degree, b_signal))
+                    if b_degree < degree || (b_degree == degree && b_signal > *signal) =>
+                {
+                    Some((b_degree, b_signal))
+                }
+                _ => Some((degree, *signal)),
+            };
+        }
+        best.map(|(_, signal)| signal)
+    }
+
+    // Makes the coefficient of signal in pivot equal to 1 and removes signal from
+    // every row accepted by affects. Returns the rows that were modified.
+    fn eliminate(&mut self, pivot: usize, signal: usize, affects: &dyn Fn(usize) -> bool) -> Vec<usize> {
+        let constant = C::constant_coefficient();
+        let field = self.field;
+        let coefficient = self.rows[pivot].get(&signal).unwrap().clone();
+        for value in self.rows[pivot].values_mut() {
+            *value = modular_arithmetic::div(value, &coefficient, field).unwrap();
+        }
+        self.pivots[pivot] = Some(signal);
+        let pivot_row = self.rows[pivot].clone();
+        let targets: Vec<usize> = self.columns.get(&signal).map_or(Vec::new(), |rows| {
+            rows.iter().cloned().filter(|r| *r != pivot && affects(*r)).collect()
+        });
+        for target in &targets {
+            let factor = self.rows[*target].get(&signal).unwrap().clone();
+            for (key, value) in &pivot_row {
+                let current = self.rows[*target].get(key).cloned().unwrap_or_else(BigInt::zero);
+                let product = modular_arithmetic::mul(&factor, value, field);
+                let new_value = modular_arithmetic::sub(&current, &product, field);
+                if new_value.is_zero() {
+                    self.rows[*target].remove(key);
+                    if *key != constant {
+                        self.columns.get_mut(key).unwrap().remove(target);
+                    }
+                } else {
+                    self.rows[*target].insert(*key, new_value);
+                    if *key != constant {
+                        self.columns.entry(*key).or_default().insert(*target);
+                    }
+                }
+            }
+        }
+        targets
+    }
+}
+
+pub fn full_gaussian_elimination<T>(config: Config<T>) -> (Simplified, EliminationReport)
+where
+    T: AsRef<HashSet<usize>>,
+{
+    let forbidden = config.forbidden.as_ref();
+    let field = config.field;
+    let mut system = SparseSystem::new(config.constraints, &field);
+    let no_rows = system.rows.len();
+    let mut all_signals: Vec<usize> = system.columns.keys().cloned().collect();
+    all_signals.sort_unstable();
+
+    // Phase 1: substitutions over non forbidden signals
+    let mut deferred = vec![false; no_rows];
+    let mut queue = BinaryHeap::new();
+    for row in 0..no_rows {
+        queue.push(Reverse((system.no_signals(row), row)));
+    }
+    while let Some(Reverse((len, row))) = queue.pop() {
+        if system.pivots[row].is_some() || deferred[row] || len != system.no_signals(row) {
+            continue;
+        }
+        if len == 0 {
+            continue;
+        }
+        let not_forbidden = |s: usize| !forbidden.contains(&s);
+        match system.choose_pivot(row, &not_forbidden) {
+            Some(signal) => {
+                // deferred rows never contain non forbidden signals
+                let modified = system.eliminate(row, signal, &|_| true);
+                for r in modified {
+                    if system.pivots[r].is_none() {
+                        queue.push(Reverse((system.no_signals(r), r)));
+                    }
+                }
+            }
+            None => deferred[row] = true,
+        }
+    }
+
+    // Phase 2: rows with forbidden signals only are reduced among themselves
+    let mut rank = system.pivots.iter().filter(|p| p.is_some()).count();
+    let mut reduced = vec![false; no_rows];
+    for row in 0..no_rows {
+        if !deferred[row] || system.no_signals(row) == 0 {
+            continue;
+        }
+        let any_signal = |_: usize| true;
+        if let Some(signal) = system.choose_pivot(row, &any_signal) {
+            let is_deferred = |r: usize| deferred[r] && !reduced[r];
+            system.eliminate(row, signal, &is_deferred);
+            reduced[row] = true;
+            rank += 1;
+        }
+    }
+
+    let mut substitutions = LinkedList::new();
+    let mut removed = LinkedList::new();
+    let mut constraints = LinkedList::new();
+    let mut report = EliminationReport {
+        rank,
+        redundant: LinkedList::new(),
+        inconsistent: LinkedList::new(),
+        free_signals: LinkedList::new(),
+    };
+    let mut determined = HashSet::new();
+    let rows = std::mem::take(&mut system.rows);
+    for (row, expression) in rows.into_iter().enumerate() {
+        if expression.is_empty() {
+            report.redundant.push_back(row);
+            continue;
+        }
+        if !expression.keys().any(|s| *s != C::constant_coefficient()) {
+            report.inconsistent.push_back(row);
+        }
+        let constraint = C::new(HashMap::new(), HashMap::new(), expression);
+        match system.pivots[row] {
+            Some(signal) if !deferred[row] => {
+                determined.insert(signal);
+                removed.push_back(signal);
+                substitutions.push_back(C::clear_signal_from_linear(constraint, &signal, &field));
+            }
+            Some(signal) => {
+                determined.insert(signal);
+                constraints.push_back(constraint);
+            }
+            None => constraints.push_back(constraint),
+        }
+    }
+    for signal in all_signals {
+        if !determined.contains(&signal) {
+            report.free_signals.push_back(signal);
+        }
+    }
+    (Simplified { constraints, substitutions, removed }, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    const FIELD: &str = "257";
+
+    fn linear(coefficients: &[(usize, i64)]) -> C {
+        let mut c = HashMap::new();
+        for (signal, value) in coefficients {
+            c.insert(*signal, BigInt::from(*value));
+        }
+        C::new(HashMap::new(), HashMap::new(), c)
+    }
+
+    #[test]
+    fn gaussian_elimination_reports_rank_and_redundancy() {
+        let field = BigInt::parse_bytes(FIELD.as_bytes(), 10)
+            .expect("generating the big int was not possible");
+        // x1 - x2 = 0, x2 - x3 = 0, x1 - x3 = 0
+        let mut constraints = LinkedList::new();
+        constraints.push_back(linear(&[(1, 1), (2, -1)]));
+        constraints.push_back(linear(&[(2, 1), (3, -1)]));
+        constraints.push_back(linear(&[(1, 1), (3, -1)]));
+        let forbidden = Arc::new(HashSet::new());
+        let config = Config { field, constraints, forbidden };
+        let (simplified, report) = full_gaussian_elimination(config);
+        assert_eq!(report.rank, 2);
+        assert_eq!(report.redundant.len(), 1);
+        assert!(report.inconsistent.is_empty());
+        assert_eq!(report.free_signals.len(), 1);
+        assert_eq!(simplified.substitutions.len(), 2);
+        assert!(simplified.constraints.is_empty());
+        let removed: HashSet<usize> = simplified.removed.iter().cloned().collect();
+        for substitution in &simplified.substitutions {
+            for signal in substitution.to().keys() {
+                assert!(!removed.contains(signal));
+            }
+        }
+    }
+
+    #[test]
+    fn gaussian_elimination_keeps_forbidden_signals() {
+        let field = BigInt::parse_bytes(FIELD.as_bytes(), 10)
+            .expect("generating the big int was not possible");
+        let constant = C::constant_coefficient();
+        // x1 + x2 - 3 = 0, 2*x1 + 2*x2 - 5 = 0 with x1, x2 forbidden
+        let mut constraints = LinkedList::new();
+        constraints.push_back(linear(&[(1, 1), (2, 1), (constant, -3)]));
+        constraints.push_back(linear(&[(1, 2), (2, 2), (constant, -5)]));
+        let mut forbidden = HashSet::new();
+        forbidden.insert(1);
+        forbidden.insert(2);
+        let config = Config { field, constraints, forbidden: Arc::new(forbidden) };
+        let (simplified, report) = full_gaussian_elimination(config);
+        assert_eq!(report.rank, 1);
+        assert_eq!(report.inconsistent.len(), 1);
+        assert!(simplified.substitutions.is_empty());
+        assert_eq!(simplified.constraints.len(), 2);
+    }
+}
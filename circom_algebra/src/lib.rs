@@ -2,5 +2,6 @@ pub extern crate num_bigint_dig as num_bigint;
 pub extern crate num_traits;
 pub mod algebra;
 pub mod constraint_storage;
+pub mod gaussian_elimination;
 pub mod modular_arithmetic;
 pub mod simplification_utils;
@@ -70,6 +70,68 @@ pub fn prefix_sub(elem: &BigInt, field: &BigInt) -> BigInt {
     mul(elem, &minus_one, field)
 }
 
+// Legendre symbol of elem in a prime field: 0 when elem is 0, 1 when it is a non zero
+// square and -1 otherwise
+pub fn legendre_symbol(elem: &BigInt, field: &BigInt) -> i32 {
+    let elem = modulus(elem, field);
+    if elem == BigInt::from(0) {
+        return 0;
+    }
+    let exp = (field - 1) / 2;
+    if pow(&elem, &exp, field) == BigInt::from(1) {
+        1
+    } else {
+        -1
+    }
+}
+// Square root in a prime field (Tonelli-Shanks), None when elem is not a square.
+// The other root is its opposite.
+pub fn sqrt(elem: &BigInt, field: &BigInt) -> Option<BigInt> {
+    let zero = BigInt::from(0);
+    let one = BigInt::from(1);
+    let two = BigInt::from(2);
+    let elem = modulus(elem, field);
+    if elem == zero || field == &two {
+        return Some(elem);
+    }
+    if legendre_symbol(&elem, field) != 1 {
+        return None;
+    }
+    // field - 1 = q * 2^s with q odd
+    let mut q = field - 1;
+    let mut s = 0;
+    while &q % &two == zero {
+        q /= &two;
+        s += 1;
+    }
+    if s == 1 {
+        return Some(pow(&elem, &((field + 1) / 4), field));
+    }
+    let mut z = two.clone();
+    while legendre_symbol(&z, field) != -1 {
+        z += 1;
+    }
+    let mut m = s;
+    let mut c = pow(&z, &q, field);
+    let mut t = pow(&elem, &q, field);
+    let mut root = pow(&elem, &((&q + 1) / 2), field);
+    while t != one {
+        // the least i such that t^(2^i) = 1
+        let mut i = 0;
+        let mut t_pow = t.clone();
+        while t_pow != one {
+            t_pow = mul(&t_pow, &t_pow, field);
+            i += 1;
+        }
+        let b = pow(&c, &num_traits::pow(two.clone(), m - i - 1), field);
+        m = i;
+        c = mul(&b, &b, field);
+        t = mul(&t, &c, field);
+        root = mul(&root, &b, field);
+    }
+    Some(root)
+}
+
 //Bit operations
 
 // 256 bit complement
@@ -246,4 +308,31 @@ mod tests {
         assert!(zero < two);
         assert!(as_bool(&lesser_eq(&zero, &two, &field), &field));
     }
+    #[test]
+    fn legendre_symbol_test() {
+        let field = BigInt::parse_bytes(FIELD.as_bytes(), 10)
+            .expect("generating the big int was not possible");
+        assert_eq!(legendre_symbol(&BigInt::from(0), &field), 0);
+        assert_eq!(legendre_symbol(&BigInt::from(-4), &field), 1);
+        // 3 generates the multiplicative group of F_257
+        assert_eq!(legendre_symbol(&BigInt::from(3), &field), -1);
+        let squares = (1..257).filter(|a| legendre_symbol(&BigInt::from(*a), &field) == 1).count();
+        assert_eq!(squares, 128);
+    }
+    #[test]
+    fn sqrt_test() {
+        // 257 - 1 = 2^8 needs the Tonelli-Shanks loop, 263 = 3 mod 4 does not
+        for prime in [FIELD, "263"] {
+            let field = BigInt::parse_bytes(prime.as_bytes(), 10)
+                .expect("generating the big int was not possible");
+            let mut elem = BigInt::from(0);
+            while elem < field {
+                match sqrt(&elem, &field) {
+                    Some(root) => assert_eq!(mul(&root, &root, &field), elem),
+                    None => assert_eq!(legendre_symbol(&elem, &field), -1),
+                }
+                elem += 1;
+            }
+        }
+    }
 }
@@ -81,20 +81,23 @@ fn treat_constraint(
     }
 }
 
+// Deleted signals are preferred, ties are broken by the greatest signal so the
+// choice does not depend on the iteration order of the constraint
 fn take_signal(signals: &SignalDefinition, constraint: &C) -> Option<usize> {
     let mut ret = Option::None;
+    let mut deleted = Option::None;
     for k in constraint.c().keys() {
         if signals.can_be_taken(*k) {
             if signals.is_deleted(*k) {
-                ret = Some(*k);
-                break;
+                let new_v = deleted.map_or(*k, |v| std::cmp::max(*k, v));
+                deleted = Some(new_v);
             } else {
                 let new_v = ret.map_or(*k, |v| std::cmp::max(*k, v));
                 ret = Some(new_v);
             }
         }
     }
-    ret
+    deleted.or(ret)
 }
 
 fn take_substitutions_to_be_applied<'a>(sh: &'a HashMap<usize, S>, subs: &S) -> Vec<&'a S> {
@@ -189,6 +192,8 @@ where
     let mut holder = SH::new();
     substitution_process(&mut signals, &mut constraints, &mut holder, &field);
     let non_overlapping = create_nonoverlapping_substitutions(holder, &field);
+    let mut non_overlapping: Vec<(usize, S)> = non_overlapping.into_iter().collect();
+    non_overlapping.sort_by_key(|(s, _)| *s);
     let mut substitutions = LinkedList::new();
     let mut removed = LinkedList::new();
     for (s, v) in non_overlapping {
@@ -0,0 +1,279 @@
+use crate::boolean_signals::booleanity_constraints;
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::{One, Zero};
+use std::collections::{HashMap, HashSet};
+use super::{ConstraintStorage, C};
+
+/*
+    Detection of bit decompositions: a linear constraint k*(sum 2^i * b_i) + rest = 0
+    where every b_i is boolean states that b_0..b_{n-1} are the bits of the value
+    -rest/k. When 2^n < p the bits of a value are unique, so two decompositions with
+    the same width and the same value have equal bits.
+*/
+
+pub struct BitDecomposition {
+    // id of the linear constraint in its storage
+    pub constraint: usize,
+    // bits[i] is the signal with weight 2^i
+    pub bits: Vec<usize>,
+    // linear expression of the decomposed value
+    pub value: HashMap<usize, BigInt>,
+}
+
+pub struct BitDecompositionReport {
+    pub found: usize,
+    pub merged: usize,
+}
+
+fn canonical(value: &BigInt, field: &BigInt) -> BigInt {
+    modular_arithmetic::add(value, &BigInt::zero(), field)
+}
+
+// 2^i -> i for every i such that 2^(i+1) < field
+fn powers_of_two(field: &BigInt) -> HashMap<BigInt, usize> {
+    let mut powers = HashMap::new();
+    let mut power = BigInt::one();
+    let mut exponent = 0;
+    while &(&power * BigInt::from(2)) < field {
+        powers.insert(power.clone(), exponent);
+        power *= BigInt::from(2);
+        exponent += 1;
+    }
+    powers
+}
+
+fn as_decomposition(
+    c_id: usize,
+    constraint: &C,
+    booleans: &HashSet<usize>,
+    powers: &HashMap<BigInt, usize>,
+    field: &BigInt,
+) -> Option<BitDecomposition> {
+    if !C::is_linear(constraint) {
+        return None;
+    }
+    let mut terms = Vec::new();
+    for (signal, value) in constraint.c() {
+        let value = canonical(value, field);
+        if !value.is_zero() {
+            terms.push((*signal, value));
+        }
+    }
+    terms.sort();
+    let candidates: Vec<&(usize, BigInt)> = terms.iter().filter(|(s, _)| booleans.contains(s)).collect();
+    let mut best: Option<(BigInt, Vec<usize>)> = None;
+    for (_, scale) in &candidates {
+        let mut by_weight = HashMap::new();
+        for (signal, value) in &candidates {
+            let ratio = modular_arithmetic::div(value, scale, field).unwrap();
+            if let Some(exponent) = powers.get(&ratio) {
+                by_weight.entry(*exponent).or_insert(*signal);
+            }
+        }
+        let mut bits = Vec::new();
+        while let Some(signal) = by_weight.get(&bits.len()) {
+            bits.push(*signal);
+        }
+        if bits.len() >= 2 && best.as_ref().is_none_or(|(_, b)| b.len() < bits.len()) {
+            best = Some((scale.clone(), bits));
+        }
+    }
+    let (scale, bits) = best?;
+    let in_bits: HashSet<usize> = bits.iter().cloned().collect();
+    let mut value = HashMap::new();
+    for (signal, coefficient) in terms {
+        if !in_bits.contains(&signal) {
+            let coefficient = modular_arithmetic::div(&coefficient, &scale, field).unwrap();
+            value.insert(signal, modular_arithmetic::prefix_sub(&coefficient, field));
+        }
+    }
+    Some(BitDecomposition { constraint: c_id, bits, value })
+}
+
+pub fn find_bit_decompositions(
+    storage: &ConstraintStorage,
+    booleans: &HashSet<usize>,
+    field: &BigInt,
+) -> Vec<BitDecomposition> {
+    let powers = powers_of_two(field);
+    let mut decompositions = Vec::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if let Some(decomposition) = as_decomposition(c_id, &constraint, booleans, &powers, field) {
+            decompositions.push(decomposition);
+        }
+    }
+    decompositions
+}
+
+fn find_representative(aliases: &mut HashMap<usize, usize>, signal: usize) -> usize {
+    let parent = *aliases.get(&signal).unwrap_or(&signal);
+    if parent == signal {
+        return signal;
+    }
+    let representative = find_representative(aliases, parent);
+    aliases.insert(signal, representative);
+    representative
+}
+
+// Signals related by constraints of the form a*x - a*y = 0
+fn build_aliases(storage: &ConstraintStorage, field: &BigInt) -> HashMap<usize, usize> {
+    let constant = C::constant_coefficient();
+    let mut aliases = HashMap::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if !C::is_linear(&constraint) {
+            continue;
+        }
+        let terms: Vec<(usize, BigInt)> = constraint
+            .c()
+            .iter()
+            .map(|(s, v)| (*s, canonical(v, field)))
+            .filter(|(_, v)| !v.is_zero())
+            .collect();
+        if terms.len() == 2
+            && terms.iter().all(|(s, _)| *s != constant)
+            && terms[0].1 == modular_arithmetic::prefix_sub(&terms[1].1, field)
+        {
+            let x = find_representative(&mut aliases, terms[0].0);
+            let y = find_representative(&mut aliases, terms[1].0);
+            if x != y {
+                aliases.insert(std::cmp::max(x, y), std::cmp::min(x, y));
+            }
+        }
+    }
+    aliases
+}
+
+fn value_key(value: &HashMap<usize, BigInt>, aliases: &mut HashMap<usize, usize>, field: &BigInt) -> Vec<(usize, BigInt)> {
+    let mut renamed: HashMap<usize, BigInt> = HashMap::new();
+    for (signal, coefficient) in value {
+        let representative = find_representative(aliases, *signal);
+        let current = renamed.remove(&representative).unwrap_or_else(BigInt::zero);
+        let new_value = modular_arithmetic::add(&current, coefficient, field);
+        if !new_value.is_zero() {
+            renamed.insert(representative, new_value);
+        }
+    }
+    let mut key: Vec<(usize, BigInt)> = renamed.into_iter().collect();
+    key.sort();
+    key
+}
+
+// Merges the decompositions of linear that have the same width and the same value
+// (modulo aliases). The bits of every duplicate are equated to the bits of the first
+// decomposition through new linear constraints added to linear, and the duplicate
+// decomposition and the booleanity constraints of its bits (in non_linear) are removed.
+// A pair of bits that are both forbidden can not be substituted, so decompositions
+// needing it are left untouched.
+pub fn merge_bit_decompositions(
+    linear: &mut ConstraintStorage,
+    non_linear: &mut ConstraintStorage,
+    forbidden: &HashSet<usize>,
+    field: &BigInt,
+) -> BitDecompositionReport {
+    let booleanity = booleanity_constraints(non_linear, field);
+    let booleans: HashSet<usize> = booleanity.keys().cloned().collect();
+    let decompositions = find_bit_decompositions(linear, &booleans, field);
+    let mut report = BitDecompositionReport { found: decompositions.len(), merged: 0 };
+    let mut aliases = build_aliases(linear, field);
+    let mut first: HashMap<(usize, Vec<(usize, BigInt)>), usize> = HashMap::new();
+    // signals whose booleanity was removed / is needed by a merge
+    let mut dropped = HashSet::new();
+    let mut relied = HashSet::new();
+    for (pos, decomposition) in decompositions.iter().enumerate() {
+        let key = (decomposition.bits.len(), value_key(&decomposition.value, &mut aliases, field));
+        let original = match first.get(&key) {
+            Some(original) => &decompositions[*original],
+            None => {
+                first.insert(key, pos);
+                continue;
+            }
+        };
+        let pairs: Vec<(usize, usize)> = original
+            .bits
+            .iter()
+            .cloned()
+            .zip(decomposition.bits.iter().cloned())
+            .filter(|(bit, duplicate)| bit != duplicate)
+            .collect();
+        // the pairs of this merge count as well: a duplicate that is also a bit of the
+        // original (permuted bits) would drop the booleanity another pair relies on
+        let original_bits: HashSet<usize> = original.bits.iter().cloned().collect();
+        let (mut merge_dropped, mut merge_relied) = (dropped.clone(), relied.clone());
+        let can_merge = pairs.iter().all(|(bit, duplicate)| {
+            let valid = (!forbidden.contains(bit) || !forbidden.contains(duplicate))
+                && !original_bits.contains(duplicate)
+                && !merge_dropped.contains(bit)
+                && !merge_relied.contains(duplicate);
+            merge_dropped.insert(*duplicate);
+            merge_relied.insert(*bit);
+            valid
+        });
+        if !can_merge {
+            continue;
+        }
+        for (bit, duplicate) in pairs {
+            let mut equality = HashMap::new();
+            equality.insert(bit, BigInt::one());
+            equality.insert(duplicate, modular_arithmetic::prefix_sub(&BigInt::one(), field));
+            linear.add_constraint(C::new(HashMap::new(), HashMap::new(), equality));
+            for c_id in booleanity.get(&duplicate).into_iter().flatten() {
+                non_linear.replace(*c_id, C::empty());
+            }
+        }
+        dropped = merge_dropped;
+        relied = merge_relied;
+        linear.replace(decomposition.constraint, C::empty());
+        report.merged += 1;
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{field, linear, quadratic, storage};
+
+    // b*(b - 1) = 0 for every bit
+    fn booleanity(bits: &[usize]) -> ConstraintStorage {
+        storage(bits.iter().map(|b| quadratic(&[(*b, 1)], &[(*b, 1), (0, -1)], &[])).collect())
+    }
+
+    fn remaining(storage: &ConstraintStorage) -> usize {
+        storage.get_ids().into_iter().filter(|c_id| !storage.read_constraint(*c_id).unwrap().is_empty()).count()
+    }
+
+    #[test]
+    fn merge_equal_decompositions() {
+        let field = field();
+        // v = a + 2b, v = c + 2d
+        let mut linear_storage = storage(vec![
+            linear(&[(5, 1), (1, -1), (2, -2)]),
+            linear(&[(5, 1), (3, -1), (4, -2)]),
+        ]);
+        let mut non_linear = booleanity(&[1, 2, 3, 4]);
+        let report = merge_bit_decompositions(&mut linear_storage, &mut non_linear, &HashSet::new(), &field);
+        assert_eq!(report.found, 2);
+        assert_eq!(report.merged, 1);
+        // a = c and b = d replace the second decomposition, c and d are no longer checked
+        assert_eq!(remaining(&linear_storage), 3);
+        assert_eq!(remaining(&non_linear), 2);
+    }
+
+    #[test]
+    fn permuted_bits_are_not_merged() {
+        let field = field();
+        // v = a + 2b, v = b + 2a
+        let mut linear_storage = storage(vec![
+            linear(&[(5, 1), (1, -1), (2, -2)]),
+            linear(&[(5, 1), (2, -1), (1, -2)]),
+        ]);
+        let mut non_linear = booleanity(&[1, 2]);
+        let report = merge_bit_decompositions(&mut linear_storage, &mut non_linear, &HashSet::new(), &field);
+        assert_eq!(report.merged, 0);
+        assert_eq!(remaining(&linear_storage), 2);
+        assert_eq!(remaining(&non_linear), 2);
+    }
+}
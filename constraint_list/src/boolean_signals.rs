@@ -0,0 +1,141 @@
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::Zero;
+use std::collections::{HashMap, HashSet};
+use super::{ConstraintStorage, C};
+
+/*
+    Detection of booleanity constraints. Any constraint whose polynomial A*B - C is
+    k*(x^2 - x) with k != 0 forces x to be 0 or 1, whatever its algebraic form is
+    (x*(x-1) = 0, x*x = x, (2x)*(x-1) = 0, (1-x)*x = 0, ...).
+*/
+
+pub struct BooleanReport {
+    // number of signals that are proven to be boolean by some constraint
+    pub signals: usize,
+    // booleanity constraints removed because they were repeated
+    pub duplicated: usize,
+}
+
+fn canonical(value: &BigInt, field: &BigInt) -> BigInt {
+    modular_arithmetic::add(value, &BigInt::zero(), field)
+}
+
+// Splits an expression with a single signal into (coefficient of signal, constant)
+fn affine_in_signal(expression: &HashMap<usize, BigInt>, signal: usize, field: &BigInt) -> Option<(BigInt, BigInt)> {
+    let constant = C::constant_coefficient();
+    let mut coefficient = BigInt::zero();
+    let mut independent = BigInt::zero();
+    for (s, value) in expression {
+        if *s == constant {
+            independent = canonical(value, field);
+        } else if *s == signal {
+            coefficient = canonical(value, field);
+        } else if !canonical(value, field).is_zero() {
+            return None;
+        }
+    }
+    Some((coefficient, independent))
+}
+
+// Returns the signal x if the constraint is k*(x^2 - x) = 0
+pub fn boolean_signal(constraint: &C, field: &BigInt) -> Option<usize> {
+    let constant = C::constant_coefficient();
+    let signal = *constraint
+        .a()
+        .iter()
+        .find(|(s, v)| **s != constant && !canonical(v, field).is_zero())?
+        .0;
+    let (a1, a0) = affine_in_signal(constraint.a(), signal, field)?;
+    let (b1, b0) = affine_in_signal(constraint.b(), signal, field)?;
+    let (c1, c0) = affine_in_signal(constraint.c(), signal, field)?;
+    let quadratic = modular_arithmetic::mul(&a1, &b1, field);
+    if quadratic.is_zero() {
+        return None;
+    }
+    let linear = modular_arithmetic::add(
+        &modular_arithmetic::mul(&a1, &b0, field),
+        &modular_arithmetic::mul(&a0, &b1, field),
+        field,
+    );
+    let linear = modular_arithmetic::sub(&linear, &c1, field);
+    let independent = modular_arithmetic::sub(&modular_arithmetic::mul(&a0, &b0, field), &c0, field);
+    let minus_quadratic = modular_arithmetic::prefix_sub(&quadratic, field);
+    if independent.is_zero() && linear == minus_quadratic {
+        Some(signal)
+    } else {
+        None
+    }
+}
+
+pub fn boolean_signals(storage: &ConstraintStorage, field: &BigInt) -> HashSet<usize> {
+    booleanity_constraints(storage, field).into_keys().collect()
+}
+
+// boolean signal -> ids of the constraints that state its booleanity
+pub fn booleanity_constraints(storage: &ConstraintStorage, field: &BigInt) -> HashMap<usize, Vec<usize>> {
+    let mut booleanity: HashMap<usize, Vec<usize>> = HashMap::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if let Some(signal) = boolean_signal(&constraint, field) {
+            booleanity.entry(signal).or_default().push(c_id);
+        }
+    }
+    booleanity
+}
+
+// Keeps exactly one booleanity constraint per signal. The aliases y = x and y = 1 - x
+// have been substituted when this runs, so their booleanity constraints are repeated
+// ones of x.
+pub fn remove_duplicated_booleanity(storage: &mut ConstraintStorage, field: &BigInt) -> BooleanReport {
+    let mut report = BooleanReport { signals: 0, duplicated: 0 };
+    let mut booleanity = HashSet::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if let Some(signal) = boolean_signal(&constraint, field) {
+            if !booleanity.insert(signal) {
+                storage.replace(c_id, C::empty());
+                report.duplicated += 1;
+            }
+        }
+    }
+    report.signals = booleanity.len();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{field, linear, quadratic, storage};
+
+    #[test]
+    fn booleanity_in_any_form() {
+        let field = field();
+        // x*(x - 1) = 0, x*x = x, (2x)*(x - 1) = 0, (1 - x)*x = 0
+        assert_eq!(boolean_signal(&quadratic(&[(1, 1)], &[(1, 1), (0, -1)], &[]), &field), Some(1));
+        assert_eq!(boolean_signal(&quadratic(&[(1, 1)], &[(1, 1)], &[(1, 1)]), &field), Some(1));
+        assert_eq!(boolean_signal(&quadratic(&[(1, 2)], &[(1, 1), (0, -1)], &[]), &field), Some(1));
+        assert_eq!(boolean_signal(&quadratic(&[(0, 1), (1, -1)], &[(1, 1)], &[]), &field), Some(1));
+        // x*(x - 2) = 0 and x*y = 0 are not booleanity constraints
+        assert_eq!(boolean_signal(&quadratic(&[(1, 1)], &[(1, 1), (0, -2)], &[]), &field), None);
+        assert_eq!(boolean_signal(&quadratic(&[(1, 1)], &[(2, 1)], &[]), &field), None);
+    }
+
+    #[test]
+    fn repeated_booleanity_is_removed() {
+        let field = field();
+        // the booleanity of x is stated twice, the one of y = 1 - x once
+        let mut constraints = storage(vec![
+            quadratic(&[(1, 1)], &[(1, 1), (0, -1)], &[]),
+            quadratic(&[(1, 1)], &[(1, 1)], &[(1, 1)]),
+            quadratic(&[(2, 1)], &[(2, 1), (0, -1)], &[]),
+            linear(&[(2, 1), (1, 1), (0, -1)]),
+        ]);
+        let report = remove_duplicated_booleanity(&mut constraints, &field);
+        assert_eq!(report.duplicated, 1);
+        assert_eq!(report.signals, 2);
+        assert!(!constraints.read_constraint(0).unwrap().is_empty());
+        assert!(constraints.read_constraint(1).unwrap().is_empty());
+        assert!(!constraints.read_constraint(2).unwrap().is_empty());
+    }
+}
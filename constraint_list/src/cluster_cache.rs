@@ -0,0 +1,168 @@
+use circom_algebra::algebra::{get_hash, HashConstraint};
+use circom_algebra::num_bigint::BigInt;
+use std::collections::{HashMap, LinkedList};
+use std::sync::Mutex;
+use super::{ConstraintStorage, C};
+
+/*
+    Cache of the deductions of the non-linear clusters that are identical up to a
+    renaming of their signals (the clusters of repeated templates). The signals of a
+    cluster are relabeled in order of first occurrence (0 is kept for the constant),
+    the relabeled constraints and their coefficients are the key of the cache, and the
+    deduction is always computed over the relabeled cluster, so the result only depends
+    on the shape and is translated back through the relabeling.
+*/
+
+type Shape = Vec<HashConstraint>;
+
+pub struct CanonicalCluster {
+    shape: Shape,
+    // canonical label -> signal of the cluster
+    to_original: Vec<usize>,
+    // position in the cluster -> prev_id of the constraint
+    prev_ids: Vec<usize>,
+}
+
+#[derive(Clone)]
+pub struct CachedDeduction {
+    pub linear: LinkedList<C>,
+    pub deletable: LinkedList<usize>,
+}
+
+#[derive(Default)]
+struct CacheContent {
+    shapes: HashMap<Shape, CachedDeduction>,
+    hits: usize,
+    misses: usize,
+}
+
+#[derive(Default)]
+pub struct ClusterCache {
+    content: Mutex<CacheContent>,
+}
+
+fn relabel(expression: &HashMap<usize, BigInt>, labels: &HashMap<usize, usize>) -> HashMap<usize, BigInt> {
+    expression.iter().map(|(signal, value)| (labels[signal], value.clone())).collect()
+}
+
+fn relabel_constraint(constraint: &C, labels: &HashMap<usize, usize>) -> C {
+    C::new(relabel(constraint.a(), labels), relabel(constraint.b(), labels), relabel(constraint.c(), labels))
+}
+
+// Also returns the constraints relabeled, their prev_id is their position in the cluster
+pub fn canonicalize(storage: &ConstraintStorage) -> (CanonicalCluster, ConstraintStorage) {
+    let constant = C::constant_coefficient();
+    let mut labels = HashMap::new();
+    labels.insert(constant, constant);
+    let mut to_original = vec![constant];
+    let mut canonical_storage = ConstraintStorage::new();
+    let mut canonical = CanonicalCluster {
+        shape: Vec::new(),
+        to_original: Vec::new(),
+        prev_ids: Vec::new(),
+    };
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        for expression in [constraint.a(), constraint.b(), constraint.c()] {
+            let mut signals: Vec<usize> = expression.keys().cloned().collect();
+            signals.sort_unstable();
+            for signal in signals {
+                labels.entry(signal).or_insert_with(|| {
+                    to_original.push(signal);
+                    to_original.len() - 1
+                });
+            }
+        }
+        let relabeled = relabel_constraint(&constraint, &labels);
+        canonical.shape.push((get_hash(relabeled.a()), get_hash(relabeled.b()), get_hash(relabeled.c())));
+        let position = canonical.prev_ids.len();
+        canonical_storage.add_constraint_with_prev_id(relabeled, position);
+        canonical.prev_ids.push(storage.read_constraint_prev_id(c_id).unwrap());
+    }
+    canonical.to_original = to_original;
+    (canonical, canonical_storage)
+}
+
+impl CanonicalCluster {
+    // the deduction of the canonical cluster in terms of the signals of the cluster
+    pub fn translate(&self, deduction: &CachedDeduction) -> CachedDeduction {
+        let labels: HashMap<usize, usize> = self.to_original.iter().cloned().enumerate().collect();
+        CachedDeduction {
+            linear: deduction.linear.iter().map(|c| relabel_constraint(c, &labels)).collect(),
+            deletable: deduction.deletable.iter().map(|position| self.prev_ids[*position]).collect(),
+        }
+    }
+}
+
+impl ClusterCache {
+    pub fn new() -> ClusterCache {
+        ClusterCache::default()
+    }
+
+    pub fn get(&self, cluster: &CanonicalCluster) -> Option<CachedDeduction> {
+        let mut content = self.content.lock().unwrap();
+        let found = content.shapes.get(&cluster.shape).cloned();
+        if found.is_some() {
+            content.hits += 1;
+        } else {
+            content.misses += 1;
+        }
+        found
+    }
+
+    pub fn insert(&self, cluster: &CanonicalCluster, deduction: CachedDeduction) {
+        self.content.lock().unwrap().shapes.insert(cluster.shape.clone(), deduction);
+    }
+
+    // clusters solved with the cache and clusters analyzed
+    pub fn hits_and_misses(&self) -> (usize, usize) {
+        let content = self.content.lock().unwrap();
+        (content.hits, content.misses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{expression, linear, quadratic};
+
+    // x*y = z, x*y = w over the given signals, with the given prev_ids
+    fn cluster(signals: [usize; 4], prev_ids: [usize; 2], factor: i64) -> ConstraintStorage {
+        let [x, y, z, w] = signals;
+        let mut storage = ConstraintStorage::new();
+        storage.add_constraint_with_prev_id(quadratic(&[(x, factor)], &[(y, 1)], &[(z, 1)]), prev_ids[0]);
+        storage.add_constraint_with_prev_id(quadratic(&[(x, 1)], &[(y, 1)], &[(w, 1)]), prev_ids[1]);
+        storage
+    }
+
+    #[test]
+    fn renamed_clusters_share_their_deduction() {
+        let cache = ClusterCache::new();
+        let (first, canonical) = canonicalize(&cluster([1, 2, 3, 4], [10, 11], 1));
+        assert!(cache.get(&first).is_none());
+        // the deduction over the canonical signals: z = w, the second constraint is redundant
+        assert_eq!(canonical.read_constraint_prev_id(1), Some(1));
+        let deduction = CachedDeduction {
+            linear: vec![linear(&[(3, 1), (4, -1)])].into_iter().collect(),
+            deletable: vec![1].into_iter().collect(),
+        };
+        cache.insert(&first, deduction);
+
+        let (second, _) = canonicalize(&cluster([8, 5, 9, 6], [20, 21], 1));
+        let found = second.translate(&cache.get(&second).unwrap());
+        let linear: Vec<HashMap<usize, BigInt>> = found.linear.iter().map(|c| c.c().clone()).collect();
+        assert_eq!(linear, vec![expression(&[(9, 1), (6, -1)])]);
+        assert_eq!(found.deletable.into_iter().collect::<Vec<usize>>(), vec![21]);
+        assert_eq!(cache.hits_and_misses(), (1, 1));
+    }
+
+    #[test]
+    fn coefficients_are_part_of_the_shape() {
+        let cache = ClusterCache::new();
+        let (first, _) = canonicalize(&cluster([1, 2, 3, 4], [0, 1], 1));
+        cache.insert(&first, CachedDeduction { linear: LinkedList::new(), deletable: LinkedList::new() });
+        let (second, _) = canonicalize(&cluster([1, 2, 3, 4], [0, 1], 2));
+        assert!(cache.get(&second).is_none());
+        assert_eq!(cache.hits_and_misses(), (0, 1));
+    }
+}
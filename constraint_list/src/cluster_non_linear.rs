@@ -19,7 +19,7 @@ pub struct ClusterInfo{
 
 pub fn obtain_linear_constraints(config: NonLinearConfig) -> (LinkedList<C>, LinkedList<usize>) {
     let cluster_info = compute_map_monomials(&config.storage, &config.field);
-    generate_constraints(&cluster_info, &config.field)
+    generate_constraints(&cluster_info, &config.field, config.deterministic)
 }
 
 pub fn compute_map_monomials(storage: &ConstraintStorage, field: &BigInt) -> ClusterInfo{
@@ -51,9 +51,10 @@ pub fn compute_map_monomials(storage: &ConstraintStorage, field: &BigInt) -> Clu
     ClusterInfo{constraints, map_monomials_constraints}
 }
 
-pub fn generate_constraints(cluster_info: &ClusterInfo, field: &BigInt) 
+// When deterministic the monomials and the deduced constraints are taken in order
+pub fn generate_constraints(cluster_info: &ClusterInfo, field: &BigInt, deterministic: bool)
 -> (LinkedList<Constraint<usize>>, LinkedList<usize>){
-    let system_constraints = generate_system_cluster(&cluster_info.map_monomials_constraints);
+    let system_constraints = generate_system_cluster(&cluster_info.map_monomials_constraints, deterministic);
     // let mut j = 1;
     //     for x in system_constraints.clone(){
     //         println!("======== Equation number {:} ========",j);
@@ -74,7 +75,7 @@ pub fn generate_constraints(cluster_info: &ClusterInfo, field: &BigInt)
     };
     let simplified = full_simplification(config);
         
-    get_new_constraints(&simplified, &cluster_info.constraints, field)
+    get_new_constraints(&simplified, &cluster_info.constraints, field, deterministic)
 }
 
 
@@ -82,10 +83,15 @@ pub fn generate_constraints(cluster_info: &ClusterInfo, field: &BigInt)
 
 
 fn generate_system_cluster(
-    map_monomials_constraints: &HashMap<Monomial, LinkedList<(ConstraintID, BigInt)>>
+    map_monomials_constraints: &HashMap<Monomial, LinkedList<(ConstraintID, BigInt)>>,
+    deterministic: bool,
 ) -> LinkedList<Constraint<usize>>{
     let mut system_constraints = LinkedList::new();
-    for (_, list_monomial) in map_monomials_constraints{
+    let mut monomials: Vec<(&Monomial, &LinkedList<(ConstraintID, BigInt)>)> = map_monomials_constraints.iter().collect();
+    if deterministic {
+        monomials.sort_unstable_by_key(|(monomial, _)| **monomial);
+    }
+    for (_, list_monomial) in monomials{
         let mut cons_monomial = HashMap::new();
         for (c_id, coeff) in list_monomial{
             cons_monomial.insert(c_id + 1, coeff.clone()); // SE GUARDA cid +1 PARA NO USAR EL 0
@@ -101,6 +107,7 @@ fn get_new_constraints(
     simplified: &Simplified,
     storage: &Vec<(C, usize)>,
     field: &BigInt,
+    deterministic: bool,
 )-> (LinkedList<Constraint<usize>>, LinkedList<usize>)
 {
     let mut used_constraints: HashMap<ConstraintID, LinkedList<(ConstraintID, BigInt)>> = HashMap::new();
@@ -137,6 +144,10 @@ fn get_new_constraints(
 
     let mut new_constraints = LinkedList::new();
     let mut total_possible_eliminate = LinkedList::new();
+    let mut used_constraints: Vec<(ConstraintID, LinkedList<(ConstraintID, BigInt)>)> = used_constraints.into_iter().collect();
+    if deterministic {
+        used_constraints.sort_unstable_by_key(|(c_id, _)| *c_id);
+    }
     for (c_id, list_cid) in &used_constraints{
             let constraint = generate_new_constraint(*c_id, list_cid, storage, field);
             if !constraint.is_empty(){
@@ -0,0 +1,141 @@
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+use super::{ConstraintStorage, Monomial};
+
+/*
+    Statistics of the clusters processed by the simplification. Every call to
+    linear_simplification / non_linear_simplification is a round, and every job sent
+    to the thread pool during a round is recorded with its size and wall time.
+*/
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ClusterPhase {
+    // clusters of linear constraints solved by full_simplification / gaussian elimination
+    Linear,
+    // clusters of non-linear constraints sharing monomials
+    Monomial,
+    // minimal clusters from which new linear constraints are deduced
+    NonLinear,
+}
+
+impl ClusterPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClusterPhase::Linear => "linear",
+            ClusterPhase::Monomial => "monomial",
+            ClusterPhase::NonLinear => "nonlinear",
+        }
+    }
+}
+
+pub struct ClusterTiming {
+    pub round: usize,
+    pub phase: ClusterPhase,
+    pub constraints: usize,
+    // number of distinct monomials, only for the non-linear phases
+    pub monomials: Option<usize>,
+    // wall time of the simplification, without counting the monomials
+    pub time: Duration,
+}
+
+pub struct RoundHistogram {
+    pub round: usize,
+    pub phase: ClusterPhase,
+    // number of constraints of a cluster -> number of clusters of that size
+    pub sizes: BTreeMap<usize, usize>,
+}
+
+#[derive(Default)]
+pub struct ClusterStats {
+    pub clusters: Vec<ClusterTiming>,
+    // constraints discarded by ProcessedConstraints::compute_zero_constraints
+    pub zero_constraints_removed: usize,
+    rounds: usize,
+}
+
+impl ClusterStats {
+    pub fn new() -> ClusterStats {
+        ClusterStats::default()
+    }
+
+    pub fn no_rounds(&self) -> usize {
+        self.rounds
+    }
+
+    pub(crate) fn start_round(&mut self) -> usize {
+        self.rounds += 1;
+        self.rounds - 1
+    }
+
+    pub(crate) fn record(&mut self, round: usize, phase: ClusterPhase, constraints: usize, monomials: Option<usize>, time: Duration) {
+        self.clusters.push(ClusterTiming { round, phase, constraints, monomials, time });
+    }
+
+    pub fn histograms(&self) -> Vec<RoundHistogram> {
+        let mut histograms: BTreeMap<(usize, ClusterPhase), BTreeMap<usize, usize>> = BTreeMap::new();
+        for cluster in &self.clusters {
+            *histograms.entry((cluster.round, cluster.phase)).or_default().entry(cluster.constraints).or_insert(0) += 1;
+        }
+        histograms
+            .into_iter()
+            .map(|((round, phase), sizes)| RoundHistogram { round, phase, sizes })
+            .collect()
+    }
+
+    // the n clusters with the largest wall time, slowest first
+    pub fn slowest(&self, n: usize) -> Vec<&ClusterTiming> {
+        let mut clusters: Vec<&ClusterTiming> = self.clusters.iter().collect();
+        clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.time));
+        clusters.truncate(n);
+        clusters
+    }
+}
+
+pub(crate) fn count_monomials(storage: &ConstraintStorage) -> usize {
+    let mut monomials: HashSet<Monomial> = HashSet::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        monomials.extend(constraint.take_possible_cloned_monomials());
+    }
+    monomials.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{quadratic, storage};
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn histograms_by_round_and_phase() {
+        let mut stats = ClusterStats::new();
+        let first = stats.start_round();
+        stats.record(first, ClusterPhase::Monomial, 3, Some(2), millis(5));
+        stats.record(first, ClusterPhase::Linear, 2, None, millis(1));
+        stats.record(first, ClusterPhase::Linear, 2, None, millis(9));
+        let second = stats.start_round();
+        stats.record(second, ClusterPhase::Linear, 4, None, millis(3));
+        assert_eq!(stats.no_rounds(), 2);
+        let histograms = stats.histograms();
+        let keys: Vec<(usize, ClusterPhase)> = histograms.iter().map(|h| (h.round, h.phase)).collect();
+        assert_eq!(keys, vec![(0, ClusterPhase::Linear), (0, ClusterPhase::Monomial), (1, ClusterPhase::Linear)]);
+        let sizes: Vec<Vec<(usize, usize)>> = histograms.into_iter().map(|h| h.sizes.into_iter().collect()).collect();
+        assert_eq!(sizes, vec![vec![(2, 2)], vec![(3, 1)], vec![(4, 1)]]);
+        let slowest: Vec<Duration> = stats.slowest(2).iter().map(|c| c.time).collect();
+        assert_eq!(slowest, vec![millis(9), millis(5)]);
+    }
+
+    #[test]
+    fn monomials_are_counted_once() {
+        // x*y = z, y*x = w, x*(y + z) = 1
+        let cluster = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(3, 1)]),
+            quadratic(&[(2, 1)], &[(1, 1)], &[(4, 1)]),
+            quadratic(&[(1, 1)], &[(2, 1), (3, 1)], &[(0, 1)]),
+        ]);
+        assert_eq!(count_monomials(&cluster), 2);
+    }
+}
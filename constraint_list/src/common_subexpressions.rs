@@ -0,0 +1,243 @@
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::{One, Zero};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use super::{ConstraintStorage, C};
+
+/*
+    Extraction of common linear subexpressions, the reverse of the elimination. A linear
+    expression E that appears (up to a factor) as the A or B side of several constraints,
+    or inside their C side, is replaced by a fresh signal aux defined by the linear
+    constraint aux - E = 0. With n terms in E and m uses, the non-zeros go from m*n to
+    m + n + 1, and the extraction is done while this saving pays for the new constraint
+    (constraint_weight non-zeros) and the new wire.
+*/
+
+// Expression normalized so its smallest signal has coefficient 1, sorted by signal
+type Subexpression = Vec<(usize, BigInt)>;
+
+pub struct ExtractedSubexpression {
+    pub signal: usize,
+    pub expression: HashMap<usize, BigInt>,
+    pub uses: usize,
+}
+
+pub struct SubexpressionReport {
+    pub extracted: Vec<ExtractedSubexpression>,
+    pub nnz_saved: usize,
+}
+
+fn canonical(value: &BigInt, field: &BigInt) -> BigInt {
+    modular_arithmetic::add(value, &BigInt::zero(), field)
+}
+
+// (E, k) such that expression = k * E
+fn normalize(expression: &HashMap<usize, BigInt>, field: &BigInt) -> Option<(Subexpression, BigInt)> {
+    let mut terms: Subexpression = expression
+        .iter()
+        .map(|(signal, value)| (*signal, canonical(value, field)))
+        .filter(|(_, value)| !value.is_zero())
+        .collect();
+    if terms.len() < 2 {
+        return None;
+    }
+    terms.sort();
+    let factor = terms[0].1.clone();
+    let inverse = modular_arithmetic::div(&BigInt::one(), &factor, field).ok()?;
+    for (_, value) in terms.iter_mut() {
+        *value = modular_arithmetic::mul(value, &inverse, field);
+    }
+    Some((terms, factor))
+}
+
+// k such that expression contains k * subexpression
+fn contained_factor(expression: &HashMap<usize, BigInt>, subexpression: &Subexpression, field: &BigInt) -> Option<BigInt> {
+    let (first, _) = &subexpression[0];
+    let factor = canonical(expression.get(first)?, field);
+    if factor.is_zero() {
+        return None;
+    }
+    for (signal, value) in &subexpression[1..] {
+        let current = canonical(expression.get(signal)?, field);
+        if current != modular_arithmetic::mul(&factor, value, field) {
+            return None;
+        }
+    }
+    Some(factor)
+}
+
+fn replace_in(expression: &mut HashMap<usize, BigInt>, subexpression: &Subexpression, factor: BigInt, signal: usize) {
+    for (s, _) in subexpression {
+        expression.remove(s);
+    }
+    expression.insert(signal, factor);
+}
+
+fn nnz_saving(uses: usize, terms: usize, constraint_weight: usize) -> i64 {
+    let before = (uses * terms) as i64;
+    let after = (uses + terms + 1) as i64;
+    before - after - constraint_weight as i64 - 1
+}
+
+// The A and B sides that are repeated, with the number of constraints that contain
+// them in any of their sides
+fn count_uses(storage: &ConstraintStorage, definitions: &HashSet<usize>, field: &BigInt) -> BTreeMap<Subexpression, usize> {
+    let mut candidates = BTreeMap::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        for side in [constraint.a(), constraint.b()] {
+            if let Some((subexpression, _)) = normalize(side, field) {
+                *candidates.entry(subexpression).or_insert(0) += 1;
+            }
+        }
+    }
+    candidates.retain(|_, uses| *uses > 1);
+    for (subexpression, uses) in candidates.iter_mut() {
+        for c_id in storage.get_ids() {
+            if definitions.contains(&c_id) {
+                continue;
+            }
+            let constraint = storage.read_constraint(c_id).unwrap();
+            if contained_factor(constraint.c(), subexpression, field).is_some() {
+                *uses += 1;
+            }
+        }
+    }
+    candidates
+}
+
+// Fresh signals are taken from first_signal on
+pub fn extract_common_subexpressions(
+    storage: &mut ConstraintStorage,
+    first_signal: usize,
+    constraint_weight: usize,
+    field: &BigInt,
+) -> SubexpressionReport {
+    let mut report = SubexpressionReport { extracted: Vec::new(), nnz_saved: 0 };
+    let mut definitions = HashSet::new();
+    loop {
+        let candidates = count_uses(storage, &definitions, field);
+        let mut best: Option<(i64, &Subexpression, usize)> = None;
+        for (subexpression, uses) in &candidates {
+            let saving = nnz_saving(*uses, subexpression.len(), constraint_weight);
+            if saving > 0 && best.as_ref().is_none_or(|(current, _, _)| saving > *current) {
+                best = Some((saving, subexpression, *uses));
+            }
+        }
+        let (saving, subexpression, uses) = match best {
+            Some(best) => best,
+            None => break,
+        };
+
+        let signal = first_signal + report.extracted.len();
+        for c_id in storage.get_ids() {
+            if definitions.contains(&c_id) {
+                continue;
+            }
+            let constraint = storage.read_constraint(c_id).unwrap();
+            let (mut a, mut b, mut c) = (constraint.a().clone(), constraint.b().clone(), constraint.c().clone());
+            let mut changed = false;
+            for side in [&mut a, &mut b] {
+                if let Some((normalized, factor)) = normalize(side, field) {
+                    if normalized == *subexpression {
+                        *side = HashMap::new();
+                        side.insert(signal, factor);
+                        changed = true;
+                    }
+                }
+            }
+            if let Some(factor) = contained_factor(&c, subexpression, field) {
+                replace_in(&mut c, subexpression, factor, signal);
+                changed = true;
+            }
+            if changed {
+                storage.replace(c_id, C::new(a, b, c));
+            }
+        }
+
+        // aux - E = 0
+        let mut definition = HashMap::new();
+        let mut expression = HashMap::new();
+        for (s, value) in subexpression {
+            definition.insert(*s, modular_arithmetic::prefix_sub(value, field));
+            expression.insert(*s, value.clone());
+        }
+        definition.insert(signal, BigInt::one());
+        let c_id = storage.add_constraint(C::new(HashMap::new(), HashMap::new(), definition));
+        definitions.insert(c_id);
+        report.nnz_saved += saving as usize + constraint_weight + 1;
+        report.extracted.push(ExtractedSubexpression { signal, expression, uses });
+    }
+    report
+}
+
+// Value of an extracted subexpression in the witness
+pub fn evaluate(expression: &HashMap<usize, BigInt>, witness: &BTreeMap<usize, BigInt>, field: &BigInt) -> BigInt {
+    let constant = C::constant_coefficient();
+    let mut value = BigInt::zero();
+    for (signal, coefficient) in expression {
+        let signal_value = if *signal == constant {
+            BigInt::one()
+        } else {
+            witness.get(signal).cloned().unwrap_or_else(BigInt::zero)
+        };
+        value = modular_arithmetic::add(&value, &modular_arithmetic::mul(coefficient, &signal_value, field), field);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{expression, field, quadratic, storage};
+
+    // (x1 + x2 + x3 + x4)*y = z four times, the second one with the factor 2 and the
+    // last one with the sum in C
+    fn repeated_sum() -> ConstraintStorage {
+        let sum = [(1, 1), (2, 1), (3, 1), (4, 1)];
+        let double = [(1, 2), (2, 2), (3, 2), (4, 2)];
+        storage(vec![
+            quadratic(&sum, &[(5, 1)], &[(6, 1)]),
+            quadratic(&double, &[(7, 1)], &[(8, 1)]),
+            quadratic(&sum, &[(9, 1)], &[(10, 1)]),
+            quadratic(&[(11, 1)], &[(12, 1)], &[(1, 1), (2, 1), (3, 1), (4, 1), (13, 1)]),
+        ])
+    }
+
+    #[test]
+    fn repeated_sum_is_extracted() {
+        let field = field();
+        let mut constraints = repeated_sum();
+        let report = extract_common_subexpressions(&mut constraints, 20, 0, &field);
+        assert_eq!(report.extracted.len(), 1);
+        assert_eq!(report.extracted[0].signal, 20);
+        assert_eq!(report.extracted[0].uses, 4);
+        assert_eq!(report.extracted[0].expression, expression(&[(1, 1), (2, 1), (3, 1), (4, 1)]));
+        // 16 non-zeros become 4 + 4 + 1
+        assert_eq!(report.nnz_saved, 7);
+        assert_eq!(constraints.read_constraint(0).unwrap().a(), &expression(&[(20, 1)]));
+        assert_eq!(constraints.read_constraint(1).unwrap().a(), &expression(&[(20, 2)]));
+        assert_eq!(constraints.read_constraint(3).unwrap().c(), &expression(&[(20, 1), (13, 1)]));
+        let definition = constraints.read_constraint(4).unwrap();
+        assert!(C::is_linear(&definition));
+        assert_eq!(definition.c(), &expression(&[(20, 1), (1, -1), (2, -1), (3, -1), (4, -1)]));
+    }
+
+    #[test]
+    fn extraction_pays_for_the_new_constraint() {
+        let field = field();
+        let mut constraints = repeated_sum();
+        let report = extract_common_subexpressions(&mut constraints, 20, 10, &field);
+        assert!(report.extracted.is_empty());
+        assert_eq!(constraints.get_no_constraints(), 4);
+    }
+
+    #[test]
+    fn value_of_a_subexpression() {
+        let field = field();
+        let witness: BTreeMap<usize, BigInt> = [(1, 200), (2, 100)].iter().map(|(s, v)| (*s, BigInt::from(*v))).collect();
+        // 2*x1 + x2 + 3 = 503 = 246 mod 257
+        let value = evaluate(&expression(&[(1, 2), (2, 1), (0, 3)]), &witness, &field);
+        assert_eq!(value, BigInt::from(246));
+    }
+}
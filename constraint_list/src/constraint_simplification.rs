@@ -2,9 +2,23 @@
 use super::{ConstraintStorage, A, C, S, HashConstraint};
 use crate::SignalMap;
 use crate::clusters_utils::{Cluster, ClusterArena, ClusterPath};
+use crate::groebner::{is_supported, GroebnerConfig};
+use crate::boolean_signals::remove_duplicated_booleanity;
+use crate::bit_decomposition::merge_bit_decompositions;
+use crate::cluster_stats::{count_monomials, ClusterPhase, ClusterStats};
+use crate::fast_equalities::collapse_equalities;
+use crate::non_linear_worklist::NonLinearWorklist;
+use crate::cluster_cache::ClusterCache;
+use crate::degenerate_quadratics::deduce_single_roots;
+use crate::non_zero::cancel_non_zero_factors;
+use crate::witness_equalities::{candidate_equalities, prove_candidates};
+use crate::range_analysis::{infer_ranges, split_bounded_sums, SignalRanges};
+use crate::common_subexpressions::{evaluate as evaluate_subexpression, extract_common_subexpressions};
+use circom_algebra::algebra::get_hash;
+use circom_algebra::modular_arithmetic;
 
 use circom_algebra::num_bigint::BigInt;
-use std::collections::{HashMap, HashSet, LinkedList, BTreeMap};
+use std::collections::{HashMap, HashSet, LinkedList, BTreeMap, BTreeSet};
 use std::fs;
 use std::sync::Arc;
 
@@ -13,13 +27,22 @@ use std::sync::Arc;
 
 
 fn build_clusters(linear: LinkedList<C>, no_vars: usize) -> Vec<Cluster<C>> {
+    build_clusters_by(linear, no_vars, &|constraint: &C| C::take_cloned_signals(constraint))
+}
+
+// Clusters of elements that share signals, signals gives the signals of each element
+pub(crate) fn build_clusters_by<E>(
+    linear: LinkedList<E>,
+    no_vars: usize,
+    signals: &dyn Fn(&E) -> HashSet<usize>,
+) -> Vec<Cluster<E>> {
 
     let no_linear = LinkedList::len(&linear);
     let mut arena = ClusterArena::with_capacity(no_linear);
     let mut cluster_to_current = ClusterPath::with_capacity(no_linear);
     let mut signal_to_cluster = vec![no_linear; no_vars];
     for constraint in linear {
-        let signals = C::take_cloned_signals(&constraint);
+        let signals = signals(&constraint);
         let dest = ClusterArena::len(&arena);
         ClusterArena::push(&mut arena, Some(Cluster::new(constraint)));
         Vec::push(&mut cluster_to_current, dest);
@@ -42,7 +65,7 @@ fn build_clusters(linear: LinkedList<C>, no_vars: usize) -> Vec<Cluster<C>> {
     clusters
 }
 
-fn build_clusters_nonlinear(
+pub(crate) fn build_clusters_nonlinear(
     storage: &ConstraintStorage,
 ) -> LinkedList<ConstraintStorage> {
 
@@ -79,8 +102,11 @@ fn build_clusters_nonlinear(
 
             if cluster.size() > 1{
                 let mut new_storage = ConstraintStorage::new();
+                // the cluster keeps the relative order of its constraints
+                let mut constraint_ids: Vec<usize> = cluster.constraints.into_iter().collect();
+                constraint_ids.sort_unstable();
     
-                for constraint_id in cluster.constraints{
+                for constraint_id in constraint_ids{
                     let constraint = storage.read_constraint(constraint_id).unwrap();
                     let prev_constraint_id = storage.read_constraint_prev_id(constraint_id).unwrap();
                     new_storage.add_constraint_with_prev_id(constraint, prev_constraint_id);
@@ -252,20 +278,86 @@ fn rebuild_witness(max_signal: usize, deleted: HashSet<usize>) -> SignalMap {
 
 
 
+#[derive(Default)]
+struct EliminationSummary {
+    rank: usize,
+    redundant: usize,
+    inconsistent: usize,
+    free_signals: usize,
+}
+
+#[derive(Default)]
+struct FastPathSummary {
+    collapsed: usize,
+    substitutions: usize,
+    fast_time: std::time::Duration,
+    // time and number of constraints of the general path
+    general_time: std::time::Duration,
+    general_constraints: usize,
+}
+
+impl FastPathSummary {
+    // time that the general path would have spent in the collapsed constraints,
+    // minus the time spent by the union-find
+    fn estimated_saving_ms(&self) -> f64 {
+        if self.general_constraints == 0 {
+            return 0.0;
+        }
+        let per_constraint = self.general_time.as_secs_f64() * 1000.0 / self.general_constraints as f64;
+        per_constraint * self.collapsed as f64 - self.fast_time.as_secs_f64() * 1000.0
+    }
+}
+
+// Results of the jobs of the thread pool, in the order in which the jobs were sent
+// when deterministic and in the order in which they finished otherwise
+fn receive_results<T>(rx: &std::sync::mpsc::Receiver<(usize, T)>, no_jobs: usize, deterministic: bool) -> Vec<T> {
+    let mut results: Vec<(usize, T)> = (0..no_jobs).map(|_| rx.recv().unwrap()).collect();
+    if deterministic {
+        results.sort_by_key(|(id, _)| *id);
+    }
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// Options of a linear round, the summaries are only given when the option is enabled
+struct LinearRound<'a> {
+    gaussian: Option<&'a mut EliminationSummary>,
+    fast_path: Option<&'a mut FastPathSummary>,
+    deterministic: bool,
+}
+
 fn linear_simplification(
     linear: LinkedList<C>,
     forbidden: Arc<HashSet<usize>>,
     no_labels: usize,
     field: &BigInt,
+    options: LinearRound,
+    stats: Option<&mut ClusterStats>,
 ) -> (LinkedList<S>, LinkedList<C>) {
+    use circom_algebra::gaussian_elimination::full_gaussian_elimination;
     use circom_algebra::simplification_utils::full_simplification;
     use circom_algebra::simplification_utils::Config;
     use std::sync::mpsc;
+    use std::time::Instant;
     use threadpool::ThreadPool;
 
     ////println!("Cluster simplification");
     let mut cons = LinkedList::new();
     let mut substitutions = LinkedList::new();
+    let LinearRound { gaussian, mut fast_path, deterministic } = options;
+    let apply_gaussian = gaussian.is_some();
+    let (linear, fast_substitutions) = match fast_path.as_deref_mut() {
+        Some(summary) => {
+            let start = Instant::now();
+            let fast = collapse_equalities(linear, &forbidden, field);
+            summary.collapsed += fast.collapsed;
+            summary.substitutions += fast.substitutions.len();
+            summary.fast_time += start.elapsed();
+            (fast.remaining, fast.substitutions)
+        }
+        None => (linear, LinkedList::new()),
+    };
+    let general_start = Instant::now();
+    let general_constraints = linear.len();
     let clusters = build_clusters(linear, no_labels);
     let (cluster_tx, simplified_rx) = mpsc::channel();
     let pool = ThreadPool::new(num_cpus::get());
@@ -282,46 +374,98 @@ fn linear_simplification(
         };
         let job = move || {
             //println!("cluster: {}, {}", id,n);
-            let result = full_simplification(config);
+            let start = Instant::now();
+            let (result, report) = if apply_gaussian {
+                let (result, report) = full_gaussian_elimination(config);
+                (result, Some(report))
+            } else {
+                (full_simplification(config), None)
+            };
              //println!("End of cluster: {}", id);
-            cluster_tx.send(result).unwrap();
+            cluster_tx.send((id, (result, report, n, start.elapsed()))).unwrap();
         };
         ThreadPool::execute(&pool, job);
-        let _ = id;
         id += 1;
     }
     ThreadPool::join(&pool);
     //println!("Sale del tratamiento de clusters");
-    for _ in 0..no_clusters {
-        let mut result = simplified_rx.recv().unwrap();
+    let mut summary = gaussian;
+    let mut stats = stats;
+    let round = stats.as_deref_mut().map(|stats| stats.start_round());
+    for (mut result, report, size, time) in receive_results(&simplified_rx, no_clusters, deterministic) {
+        if let (Some(stats), Some(round)) = (stats.as_deref_mut(), round) {
+            stats.record(round, ClusterPhase::Linear, size, None, time);
+        }
         LinkedList::append(&mut cons, &mut result.constraints);
         LinkedList::append(&mut substitutions, &mut result.substitutions);
+        if let (Some(summary), Some(report)) = (summary.as_deref_mut(), report) {
+            summary.rank += report.rank;
+            summary.redundant += report.redundant.len();
+            summary.inconsistent += report.inconsistent.len();
+            summary.free_signals += report.free_signals.len();
+        }
+    }
+    // the representatives of the union-find may have been substituted by the general path
+    let general: HashMap<usize, &S> = substitutions.iter().map(|sub| (*sub.from(), sub)).collect();
+    let mut composed = LinkedList::new();
+    for mut substitution in fast_substitutions {
+        let signals: Vec<usize> = substitution.to().keys().cloned().collect();
+        for signal in signals {
+            if let Some(change) = general.get(&signal) {
+                S::apply_substitution(&mut substitution, change, field);
+            }
+        }
+        composed.push_back(substitution);
+    }
+    LinkedList::append(&mut substitutions, &mut composed);
+    if let Some(summary) = fast_path {
+        summary.general_time += general_start.elapsed();
+        summary.general_constraints += general_constraints;
     }
     (substitutions, cons)
 }
 
 
+// Options of the non-linear rounds that do not change from one round to the next
+struct NonLinearRound {
+    groebner: Option<GroebnerConfig>,
+    deterministic: bool,
+    cache: Option<Arc<ClusterCache>>,
+}
+
 fn non_linear_simplification(
     deduced_constraints_hash: &mut HashSet<HashConstraint>,
     clusters: LinkedList<ConstraintStorage>,
+    // linear constraints deduced outside of the clusters
+    mut deduced: LinkedList<C>,
     forbidden: Arc<HashSet<usize>>,
     field: &BigInt,
-) -> (LinkedList<S>, LinkedList<C>, LinkedList<usize>, usize) {
+    options: &NonLinearRound,
+    stats: Option<&mut ClusterStats>,
+) -> (LinkedList<S>, LinkedList<C>, LinkedList<usize>, usize, LinkedList<C>) {
     use circom_algebra::simplification_utils::full_simplification;
     use circom_algebra::simplification_utils::Config;
     use std::sync::mpsc;
+    use std::time::Instant;
     use threadpool::ThreadPool;
 
     //println!("Cluster simplification");
     ////println!("Numero total de constraints: {}", storage.get_no_constraints());
     let mut cons = LinkedList::new();
     let mut delete = LinkedList::new();
+    let mut quadratic = LinkedList::new();
+    // the deletions of each Groebner deduction wait for its linear constraints
+    let mut groebner_deductions = Vec::new();
     let mut minimal_clusters = LinkedList::new();
     let (cluster_tx, simplified_rx) = mpsc::channel();
     let pool = ThreadPool::new(num_cpus::get());
     let mut no_clusters = 0;
     // //println!("Clusters: {}", no_clusters);
     let mut id = 0;
+    let mut stats = stats;
+    let round = stats.as_deref_mut().map(|stats| stats.start_round());
+    let collect_stats = round.is_some();
+    let deterministic = options.deterministic;
 
     for cluster in clusters {
             no_clusters = no_clusters + 1;
@@ -330,22 +474,36 @@ fn non_linear_simplification(
             let config = crate::non_linear_simplification::NonLinearClustersConfig {
                 storage: cluster,
                 field: field.clone(),
+                deterministic,
             };
+            let groebner = options.groebner.clone();
             let job = move || {
-                let new_clusters = crate::non_linear_simplification::obtain_non_linear_clusters(config);
-                cluster_tx.send(new_clusters).unwrap();
+                let size = config.storage.get_no_constraints();
+                let monomials = if collect_stats { Some(count_monomials(&config.storage)) } else { None };
+                let deduction = groebner.map(|groebner| crate::non_linear_simplification::deduce_groebner_constraints(&config, &groebner));
+                // only the split into minimal clusters is timed as the monomial phase
+                let start = Instant::now();
+                let (new_clusters, removed) = crate::non_linear_simplification::obtain_non_linear_clusters_counting(config);
+                cluster_tx.send((id, (new_clusters, deduction, (size, monomials, removed, start.elapsed())))).unwrap();
             };
             ThreadPool::execute(&pool, job);
 
-            let _ = id;
             id += 1;
         
     }
     ThreadPool::join(&pool);
-    for _ in 0..no_clusters {
-        let mut new_clusters = simplified_rx.recv().unwrap();
+    for (mut new_clusters, deduction, (size, monomials, removed, time)) in receive_results(&simplified_rx, no_clusters, deterministic) {
+        if let (Some(stats), Some(round)) = (stats.as_deref_mut(), round) {
+            stats.record(round, ClusterPhase::Monomial, size, monomials, time);
+            stats.zero_constraints_removed += removed;
+        }
 
         LinkedList::append(&mut minimal_clusters, &mut new_clusters);
+        if let Some(mut deduction) = deduction {
+            let support = deduction.linear.clone();
+            LinkedList::append(&mut cons, &mut deduction.linear);
+            groebner_deductions.push((support, deduction.to_delete, deduction.quadratic));
+        }
     }
     //println!("Calculados clusters minimos. Un total de {} clusters", minimal_clusters.len());
     let mut j = 0;
@@ -364,26 +522,33 @@ fn non_linear_simplification(
             field: field.clone(),
             storage: cluster,
             forbidden: Arc::clone(&forbidden),
+            deterministic,
+            cache: options.cache.clone(),
         };
 
         let job = move || {
+            let size = config.storage.get_no_constraints();
+            let monomials = if collect_stats { Some(count_monomials(&config.storage)) } else { None };
+            let start = Instant::now();
             let (new_constraints, to_delete) = crate::non_linear_simplification::deduce_linear_constraints(config);
-            cluster_tx.send((new_constraints, to_delete)).unwrap();
+            cluster_tx.send((id, (new_constraints, to_delete, (size, monomials, start.elapsed())))).unwrap();
         };
         ThreadPool::execute(&pool, job);
 
-        let _ = id;
         id += 1;
     
     }
     ThreadPool::join(&pool);
     ////println!("Calculadas nuevas lineales");
-    for _ in 0..no_clusters {
-        let (mut new_constraints, mut new_delete) = simplified_rx.recv().unwrap();   
+    for (mut new_constraints, mut new_delete, (size, monomials, time)) in receive_results(&simplified_rx, no_clusters, deterministic) {
+        if let (Some(stats), Some(round)) = (stats.as_deref_mut(), round) {
+            stats.record(round, ClusterPhase::NonLinear, size, monomials, time);
+        }
         LinkedList::append(&mut cons, &mut new_constraints);
         LinkedList::append(&mut delete, &mut new_delete);
     }
 
+    LinkedList::append(&mut cons, &mut deduced);
     for c in &cons{
         if deduced_constraints_hash.contains(&C::get_hash_constraint(&c, field)){
             //println!("Repetida:");
@@ -406,11 +571,28 @@ fn non_linear_simplification(
 
 
     let result = full_simplification(config);
-    (result.substitutions, result.constraints, delete, num_new_linear)
+    for (support, mut to_delete, mut new_quadratic) in groebner_deductions {
+        if is_supported(&support, &result.substitutions, field) {
+            LinkedList::append(&mut delete, &mut to_delete);
+            LinkedList::append(&mut quadratic, &mut new_quadratic);
+        }
+    }
+    (result.substitutions, result.constraints, delete, num_new_linear, quadratic)
 }
 
-type SignalToConstraints = HashMap<usize, LinkedList<usize>>;
-fn build_non_linear_signal_map(non_linear: &ConstraintStorage) -> SignalToConstraints {
+pub(crate) type SignalToConstraints = HashMap<usize, LinkedList<usize>>;
+// the constraints that may be changed by the substitutions
+fn touch_substituted(worklist: &mut NonLinearWorklist, map: &SignalToConstraints, substitutions: &LinkedList<S>) {
+    for substitution in substitutions {
+        if let Some(c_ids) = map.get(substitution.from()) {
+            for c_id in c_ids {
+                worklist.touch(*c_id);
+            }
+        }
+    }
+}
+
+pub(crate) fn build_non_linear_signal_map(non_linear: &ConstraintStorage) -> SignalToConstraints {
     let mut map = SignalToConstraints::new();
     for c_id in non_linear.get_ids() {
         let constraint = non_linear.read_constraint(c_id).unwrap();
@@ -588,19 +770,285 @@ fn remove_redundant_constraints(constraint_storage: &mut ConstraintStorage, fiel
     }
 }
 
-pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut ConstraintStorage, mut forb: HashSet<usize>, no_labels: usize, max_signal: usize,  field: BigInt, apply_simp: bool,
-    witness: BTreeMap<usize, BigInt>) -> (SignalMap,BTreeMap<usize,BigInt>) {
+type ProductKey = (Vec<(usize, BigInt)>, Vec<(usize, BigInt)>);
+
+// Quadratic constraints are indexed by their normalized (A, B) pair. When two of them
+// share the product, A*B = C1 and A*B = C2 imply the linear constraint C1 = C2, so the
+// second one is dropped and the linear constraint is returned instead. Returns the
+// new linear constraints and the number of dropped constraints.
+fn merge_duplicate_products(constraint_storage: &mut ConstraintStorage, field: &BigInt) -> (LinkedList<C>, usize) {
+    let mut products: HashMap<ProductKey, HashMap<usize, BigInt>> = HashMap::new();
+    let mut linear = LinkedList::new();
+    let mut merged = 0;
+    for cid in constraint_storage.get_ids(){
+        let constraint = constraint_storage.read_constraint(cid).unwrap();
+        if C::is_empty(&constraint) || constraint.a().is_empty() || constraint.b().is_empty(){
+            continue;
+        }
+        let norm_constraint = circom_algebra::algebra::normalize(constraint, field);
+        if norm_constraint.a().is_empty() || norm_constraint.b().is_empty(){
+            continue;
+        }
+        // A*B and B*A are the same product
+        let (first, second) = (get_hash(norm_constraint.a()), get_hash(norm_constraint.b()));
+        let key = if first <= second { (first, second) } else { (second, first) };
+        match products.get(&key){
+            Some(c_first) => {
+                let mut c_diff = c_first.clone();
+                for (signal, value) in norm_constraint.c(){
+                    let current = c_diff.remove(signal).unwrap_or_else(|| BigInt::from(0));
+                    let new_value = modular_arithmetic::sub(&current, value, field);
+                    if new_value != BigInt::from(0){
+                        c_diff.insert(*signal, new_value);
+                    }
+                }
+                if !c_diff.is_empty(){
+                    linear.push_back(C::new(HashMap::new(), HashMap::new(), c_diff));
+                }
+                constraint_storage.replace(cid, C::empty());
+                merged += 1;
+            }
+            None => {
+                products.insert(key, norm_constraint.c().clone());
+            }
+        }
+    }
+    (linear, merged)
+}
+
+// A signal only defines itself in a constraint when it does not appear in A or B and
+// has a non zero coefficient in C: whatever the values of the rest of signals are,
+// there is a value of the signal that satisfies the constraint.
+fn only_defines(constraint: &C, signal: usize, field: &BigInt) -> bool {
+    let zero = BigInt::from(0);
+    let is_zero = |value: &BigInt| modular_arithmetic::add(value, &zero, field) == zero;
+    constraint.a().get(&signal).is_none_or(is_zero)
+        && constraint.b().get(&signal).is_none_or(is_zero)
+        && constraint.c().get(&signal).is_some_and(|v| !is_zero(v))
+}
+
+// Removes the constraints that are the only use of a private, non forbidden signal
+// which they just define. Removing a constraint may leave other signals with a
+// single use, so the pass is repeated until nothing changes. Returns the removed signals.
+fn remove_dead_definitions(constraint_storage: &mut ConstraintStorage, forbidden: &HashSet<usize>, field: &BigInt) -> HashSet<usize> {
+    let constant = C::constant_coefficient();
+    let mut occurrences: HashMap<usize, HashSet<usize>> = HashMap::new();
+    for cid in constraint_storage.get_ids(){
+        let constraint = constraint_storage.read_constraint(cid).unwrap();
+        for signal in C::take_cloned_signals(&constraint){
+            occurrences.entry(signal).or_default().insert(cid);
+        }
+    }
+    let mut removed = HashSet::new();
+    let mut pending: Vec<usize> = occurrences.keys().cloned().collect();
+    pending.sort_unstable();
+    while let Some(signal) = pending.pop(){
+        if signal == constant || forbidden.contains(&signal) || removed.contains(&signal){
+            continue;
+        }
+        let cid = match occurrences.get(&signal){
+            Some(cids) if cids.len() == 1 => *cids.iter().next().unwrap(),
+            _ => continue,
+        };
+        let constraint = constraint_storage.read_constraint(cid).unwrap();
+        if !only_defines(&constraint, signal, field){
+            continue;
+        }
+        constraint_storage.replace(cid, C::empty());
+        removed.insert(signal);
+        for other in C::take_cloned_signals(&constraint){
+            occurrences.get_mut(&other).unwrap().remove(&cid);
+            if other != signal{
+                pending.push(other);
+            }
+        }
+    }
+    removed
+}
+
+// A private, non forbidden signal c that only defines itself in a quadratic constraint
+// A*B = C and appears in a single linear constraint L is replaced in A*B = C by its
+// value in L, so L and c disappear (one constraint and one wire less). The quadratic
+// constraints of the signals of L are taken from the occurrence map, which is updated
+// with the rewritten constraints. Returns the remaining linear constraints and the
+// substitutions of the folded signals.
+fn fold_single_use_products(
+    linear: LinkedList<C>,
+    constraint_storage: &mut ConstraintStorage,
+    map: &mut SignalToConstraints,
+    forbidden: &HashSet<usize>,
+    field: &BigInt,
+) -> (LinkedList<C>, LinkedList<S>) {
+    let constant = C::constant_coefficient();
+    let mut linear: Vec<Option<C>> = linear.into_iter().map(Some).collect();
+    let mut linear_uses: HashMap<usize, HashSet<usize>> = HashMap::new();
+    for (l_id, constraint) in linear.iter().enumerate() {
+        for signal in C::take_cloned_signals(constraint.as_ref().unwrap()) {
+            linear_uses.entry(signal).or_default().insert(l_id);
+        }
+    }
+    let mut candidates: Vec<usize> = linear_uses.keys().cloned().collect();
+    candidates.sort_unstable();
+    let mut folded = LinkedList::new();
+    for signal in candidates {
+        if signal == constant || forbidden.contains(&signal) {
+            continue;
+        }
+        let l_id = match linear_uses.get(&signal) {
+            Some(l_ids) if l_ids.len() == 1 => *l_ids.iter().next().unwrap(),
+            _ => continue,
+        };
+        let uses: HashSet<usize> = map.get(&signal).map(|c_ids| c_ids.iter().cloned().collect()).unwrap_or_default();
+        if uses.len() != 1 {
+            continue;
+        }
+        let cid = *uses.iter().next().unwrap();
+        let mut quadratic = constraint_storage.read_constraint(cid).unwrap();
+        if C::is_linear(&quadratic) || !only_defines(&quadratic, signal, field) {
+            continue;
+        }
+        let consumer = linear[l_id].take().unwrap();
+        let substitution = C::clear_signal_from_linear(consumer.clone(), &signal, field);
+        C::apply_substitution(&mut quadratic, &substitution, field);
+        constraint_storage.replace(cid, quadratic);
+        for other in C::take_cloned_signals(&consumer) {
+            linear_uses.get_mut(&other).unwrap().remove(&l_id);
+            if other != signal {
+                let c_ids = map.entry(other).or_default();
+                if !c_ids.contains(&cid) {
+                    c_ids.push_back(cid);
+                }
+            }
+        }
+        map.remove(&signal);
+        folded.push_back(substitution);
+    }
+    (linear.into_iter().flatten().collect(), folded)
+}
+
+pub struct SimplificationConfig {
+    // deduce new linear constraints from the non-linear ones
+    pub apply_non_linear: bool,
+    // solve the linear clusters with sparse Gaussian elimination instead of full_simplification
+    pub gaussian_elimination: bool,
+    // run the bounded Groebner deduction on the non-linear clusters with at most
+    // this number of constraints (0 disables it)
+    pub groebner_cluster_size: usize,
+    pub groebner_degree: usize,
+    pub groebner_time_budget_ms: u64,
+    // S-pairs computed by each Groebner basis instead of the time budget when deterministic
+    pub groebner_pair_budget: usize,
+    // replace quadratic constraints with the same product by linear constraints
+    pub merge_duplicate_products: bool,
+    // keep a single booleanity constraint per boolean signal
+    pub boolean_detection: bool,
+    // merge the bit decompositions of the same value
+    pub bit_decompositions: bool,
+    // remove the private signals that are only used to define themselves
+    pub dead_definitions: bool,
+    // record the size and wall time of every cluster (returned by simplification)
+    pub cluster_stats: bool,
+    // number of slowest clusters shown in the report of the statistics
+    pub slowest_clusters: usize,
+    // keep the substitutions applied, in order (returned by simplification)
+    pub substitution_log: bool,
+    // solve the constraints x = y and x = k with a union-find before the linear clusters
+    pub fast_equalities: bool,
+    // same output for the same input whatever the number of threads: the results of the
+    // clusters are taken in order and the clusters keep the order of their constraints
+    pub deterministic: bool,
+    // after the first non-linear round only the clusters changed by the round are rebuilt
+    pub incremental_non_linear: bool,
+    // reuse the deductions of the non-linear clusters with the same shape up to renaming
+    pub cluster_cache: bool,
+    // undo a non-linear round (and stop the rounds) when it makes the system more expensive
+    pub speculative_elimination: bool,
+    // cost of a constraint in non-zero coefficients for the speculative rounds and
+    // the extraction of common subexpressions
+    pub constraint_weight: usize,
+    // replace the linear expressions repeated in many constraints by new signals
+    pub common_subexpressions: bool,
+    // eliminate the private signals defined by a product and used in a single linear
+    // constraint, which becomes the new definition of the product
+    pub fold_single_use_products: bool,
+    // deduce the value of the linear forms that a quadratic constraint fixes to a
+    // single root, like x in x*x = 0
+    pub degenerate_quadratics: bool,
+    // cancel the factors that are proven non-zero (x * inv = 1) between quadratic constraints
+    pub non_zero_cancellation: bool,
+    // bound the signals (booleans, sums of bounded terms) and split the linear
+    // constraints that force their terms to a bound
+    pub range_analysis: bool,
+    // propose the linear relations between signals that hold in the witnesses and apply
+    // the ones proven from the constraints
+    pub witness_equalities: bool,
+    // witnesses used to propose the relations besides the one being simplified
+    pub extra_witnesses: Vec<BTreeMap<usize, BigInt>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SystemCost {
+    pub constraints: usize,
+    pub nnz: usize,
+    pub wires: usize,
+}
+
+impl SystemCost {
+    // the fill-in of a substitution (nnz) is weighed against the constraints and wires it removes
+    pub fn total(&self, constraint_weight: usize) -> usize {
+        self.constraints * constraint_weight + self.nnz + self.wires
+    }
+}
+
+pub fn system_cost(storage: &ConstraintStorage, no_wires: usize) -> SystemCost {
+    let mut cost = SystemCost { constraints: 0, nnz: 0, wires: no_wires };
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if !C::is_empty(&constraint) {
+            cost.constraints += 1;
+            cost.nnz += constraint.a().len() + constraint.b().len() + constraint.c().len();
+        }
+    }
+    cost
+}
+
+// State of the simplification before a speculative round
+struct Speculation {
+    cost: SystemCost,
+    deleted: HashSet<usize>,
+    log_len: usize,
+    total_eliminated: usize,
+    non_linear_map: SignalToConstraints,
+    counters: [usize; 5],
+}
+
+pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut ConstraintStorage, mut forb: HashSet<usize>, no_labels: usize, max_signal: usize,  field: BigInt, config: &SimplificationConfig,
+    witness: BTreeMap<usize, BigInt>) -> (SignalMap,BTreeMap<usize,BigInt>,Option<ClusterStats>,Option<Vec<S>>) {
     use circom_algebra::simplification_utils::build_encoded_fast_substitutions;
     use circom_algebra::simplification_utils::fast_encoded_constraint_substitution;
     use std::time::SystemTime;
     use std::sync::mpsc;
     use threadpool::ThreadPool;
 
+    if config.bit_decompositions {
+        let mut linear_storage = ConstraintStorage::new();
+        for constraint in std::mem::take(&mut linear) {
+            linear_storage.add_constraint(constraint);
+        }
+        let report = merge_bit_decompositions(&mut linear_storage, constraint_storage, &forb, &field);
+        println!("Descomposiciones en bits encontradas: {}, fusionadas: {}", report.found, report.merged);
+        linear = linear_storage.extract_with(&|c| !C::is_empty(c));
+    }
+
     let mut round_id = 0;
     let _ = round_id;
     let mut apply_round = !linear.is_empty();
     let forbidden = Arc::new(std::mem::replace(&mut forb, HashSet::with_capacity(0)));
     let mut deleted = HashSet::new();
+    let mut elimination_summary = EliminationSummary::default();
+    let mut cluster_stats = if config.cluster_stats { Some(ClusterStats::new()) } else { None };
+    let mut substitution_log = if config.substitution_log { Some(Vec::new()) } else { None };
+    let mut fast_path_summary = FastPathSummary::default();
     let mut non_linear_map = if true {
         // //println!("Building non-linear map");
         let now = SystemTime::now();
@@ -612,6 +1060,30 @@ pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut Constr
         SignalToConstraints::with_capacity(0)
     };
 
+    let ranges = if config.range_analysis {
+        let ranges = infer_ranges(&linear, constraint_storage, &field);
+        let (remaining, split) = split_bounded_sums(linear, &ranges, &field);
+        println!("Señales acotadas: {}, lineales separadas por los rangos: {}", ranges.len(), split);
+        linear = remaining;
+        apply_round = !linear.is_empty();
+        ranges
+    } else {
+        SignalRanges::new()
+    };
+
+    if config.fold_single_use_products {
+        let (remaining, folded) = fold_single_use_products(linear, constraint_storage, &mut non_linear_map, &forbidden, &field);
+        println!("Productos de un solo uso plegados en su constraint lineal: {}", folded.len());
+        for sub in &folded {
+            deleted.insert(*sub.from());
+        }
+        if let Some(log) = &mut substitution_log {
+            log.extend(folded);
+        }
+        linear = remaining;
+        apply_round = !linear.is_empty();
+    }
+
 
     //println!("Comienza la simplificacion lineal.");
     while apply_round {
@@ -623,11 +1095,20 @@ pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut Constr
             Arc::clone(&forbidden),
             no_labels,
             &field,
+            LinearRound {
+                gaussian: if config.gaussian_elimination { Some(&mut elimination_summary) } else { None },
+                fast_path: if config.fast_equalities { Some(&mut fast_path_summary) } else { None },
+                deterministic: config.deterministic,
+            },
+            cluster_stats.as_mut(),
         );
         
         for sub in &substitutions {
             deleted.insert(*sub.from());
         }
+        if let Some(log) = &mut substitution_log {
+            log.extend(substitutions.iter().cloned());
+        }
         //println!("Entra en apply_substitution_to_map");
         linear = apply_substitution_to_map(
             constraint_storage,
@@ -643,13 +1124,28 @@ pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut Constr
     }
 
 
-    let mut apply_round_non_linear = apply_simp;
+    let mut apply_round_non_linear = config.apply_non_linear;
     let mut total_eliminated = 0;
     let mut linear_extracted_non_linear = 0;
     let mut linear_obtained_after_simplification = 0;
     let mut iterations_non_linear = 0;
     let mut iterations_linear = 0;
     let mut deduced_constraints = HashSet::new();
+    let mut quadratic_extracted_groebner = 0;
+    let mut merged_products = 0;
+    let mut constants_single_roots = 0;
+    let mut linear_cancelled_factors = 0;
+    let mut non_zero_expressions = 0;
+    let groebner = if config.groebner_cluster_size > 0 {
+        Some(GroebnerConfig {
+            max_constraints: config.groebner_cluster_size,
+            max_degree: config.groebner_degree,
+            time_budget: std::time::Duration::from_millis(config.groebner_time_budget_ms),
+            max_pairs: if config.deterministic { Some(config.groebner_pair_budget) } else { None },
+        })
+    } else {
+        None
+    };
 
     //println!("Comienza la normalizacion.");
     //let mut non_linear_set = build_non_linear_hashset(&mut constraint_storage, &field);
@@ -658,27 +1154,202 @@ pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut Constr
     let number_before_deduction : usize = get_number_non_empty_constraints(& constraint_storage);
     //println!("Total de constraints no lineales antes de empezar la reducción: {}",number_before_deduction);
 
+    if config.merge_duplicate_products {
+        let (mut linear, merged) = merge_duplicate_products(constraint_storage, &field);
+        merged_products += merged;
+        let mut kept_linear = 0;
+        while !linear.is_empty() {
+            while !linear.is_empty() {
+                let (substitutions, unsolved) = linear_simplification(
+                    linear,
+                    Arc::clone(&forbidden),
+                    no_labels,
+                    &field,
+                    LinearRound {
+                        gaussian: if config.gaussian_elimination { Some(&mut elimination_summary) } else { None },
+                        fast_path: if config.fast_equalities { Some(&mut fast_path_summary) } else { None },
+                        deterministic: config.deterministic,
+                    },
+                    cluster_stats.as_mut(),
+                );
+                for sub in &substitutions {
+                    deleted.insert(*sub.from());
+                }
+                if let Some(log) = &mut substitution_log {
+                    log.extend(substitutions.iter().cloned());
+                }
+                linear = apply_substitution_to_map_non_linear(
+                    constraint_storage,
+                    &mut non_linear_map,
+                    &substitutions,
+                    &field,
+                );
+                total_eliminated += substitutions.len();
+                // C1 = C2 can not be substituted when all its signals are forbidden, it
+                // takes the place of the dropped product in the system
+                for constraint in unsolved {
+                    let c_id = constraint_storage.add_constraint(constraint.clone());
+                    for signal in C::take_cloned_signals(&constraint) {
+                        non_linear_map.entry(signal).or_default().push_back(c_id);
+                    }
+                    kept_linear += 1;
+                }
+            }
+            // the substitutions may have made other products equal
+            let (new_linear, merged) = merge_duplicate_products(constraint_storage, &field);
+            merged_products += merged;
+            linear = new_linear;
+        }
+        total_eliminated += merged_products - kept_linear;
+    }
+
     //println!("Comienza la creacion de clusters.");
     let mut new_clusters  = build_clusters_nonlinear(&constraint_storage);
-    let mut apply_only_affected = true;
+    let apply_only_affected = config.incremental_non_linear;
+    let mut worklist = if apply_only_affected { Some(NonLinearWorklist::new(constraint_storage)) } else { None };
+    let mut reanalyzed_constraints = 0;
+    let mut rejected_rounds = 0;
+    let cluster_cache = if config.cluster_cache { Some(Arc::new(ClusterCache::new())) } else { None };
+    let non_linear_round = NonLinearRound {
+        groebner: groebner.clone(),
+        deterministic: config.deterministic,
+        cache: cluster_cache.clone(),
+    };
     let now = SystemTime::now();
     //println!("Termina la creacion de clusters.");
    
-    while apply_round_non_linear{
+    let mut witness_report = None;
+    let mut witness_skipped = 0;
+    let mut witness_pending = config.witness_equalities;
+    while apply_round_non_linear || witness_pending {
+        if !apply_round_non_linear {
+            // the rounds stopped deducing: the relations proven from the witnesses are
+            // applied, and the rounds go on with the constraints they simplify
+            witness_pending = false;
+            let mut signals = BTreeSet::new();
+            for c_id in constraint_storage.get_ids() {
+                let constraint = constraint_storage.read_constraint(c_id).unwrap();
+                signals.extend(C::take_cloned_signals(&constraint));
+            }
+            signals.remove(&C::constant_coefficient());
+            let witnesses: Vec<&BTreeMap<usize, BigInt>> = std::iter::once(&witness).chain(&config.extra_witnesses).collect();
+            let (candidates, skipped) = candidate_equalities(&signals, &witnesses, &field);
+            witness_skipped = skipped;
+            // the neighbourhood of a candidate bounds the number of constraints
+            let proof = GroebnerConfig {
+                max_constraints: usize::MAX,
+                max_degree: config.groebner_degree,
+                time_budget: std::time::Duration::from_millis(config.groebner_time_budget_ms),
+                max_pairs: if config.deterministic { Some(config.groebner_pair_budget) } else { None },
+            };
+            let report = prove_candidates(constraint_storage, candidates, &proof, &field);
+            let mut linear: LinkedList<C> = report.proven.iter().cloned().collect();
+            let mut applied = 0;
+            while !linear.is_empty() {
+                let (substitutions, _) = linear_simplification(
+                    linear,
+                    Arc::clone(&forbidden),
+                    no_labels,
+                    &field,
+                    LinearRound {
+                        gaussian: if config.gaussian_elimination { Some(&mut elimination_summary) } else { None },
+                        fast_path: if config.fast_equalities { Some(&mut fast_path_summary) } else { None },
+                        deterministic: config.deterministic,
+                    },
+                    cluster_stats.as_mut(),
+                );
+                for sub in &substitutions {
+                    deleted.insert(*sub.from());
+                }
+                if let Some(log) = &mut substitution_log {
+                    log.extend(substitutions.iter().cloned());
+                }
+                linear = apply_substitution_to_map_non_linear(
+                    constraint_storage,
+                    &mut non_linear_map,
+                    &substitutions,
+                    &field,
+                );
+                total_eliminated += substitutions.len();
+                applied += substitutions.len();
+            }
+            witness_report = Some(report);
+            apply_round_non_linear = config.apply_non_linear && applied > 0;
+            if apply_round_non_linear {
+                new_clusters = build_clusters_nonlinear(constraint_storage);
+                if let Some(worklist) = &mut worklist {
+                    *worklist = NonLinearWorklist::new(constraint_storage);
+                }
+            }
+            continue;
+        }
         ////println!("Numero de clusters {}", new_clusters.len());
-        let (substitutions, _, to_delete, num_new_linear) = non_linear_simplification(
+        // the whole round is undone if it makes the system worse, so the snapshot is
+        // taken before anything of the round is counted or added to the storage
+        let speculation = if config.speculative_elimination {
+            constraint_storage.begin_transaction();
+            Some(Speculation {
+                cost: system_cost(constraint_storage, max_signal - deleted.len()),
+                deleted: deleted.clone(),
+                log_len: substitution_log.as_ref().map_or(0, |log| log.len()),
+                total_eliminated,
+                non_linear_map: non_linear_map.clone(),
+                counters: [
+                    constants_single_roots,
+                    linear_cancelled_factors,
+                    linear_extracted_non_linear,
+                    quadratic_extracted_groebner,
+                    linear_obtained_after_simplification,
+                ],
+            })
+        } else {
+            None
+        };
+        let mut single_roots = if config.degenerate_quadratics {
+            let single_roots = deduce_single_roots(constraint_storage, &field);
+            constants_single_roots += single_roots.len();
+            single_roots
+        } else {
+            LinkedList::new()
+        };
+        if config.non_zero_cancellation {
+            let (mut cancelled, no_non_zero) = cancel_non_zero_factors(constraint_storage, &ranges, &field);
+            linear_cancelled_factors += cancelled.len();
+            non_zero_expressions = no_non_zero;
+            single_roots.append(&mut cancelled);
+        }
+        let (substitutions, _, to_delete, num_new_linear, quadratic) = non_linear_simplification(
             &mut deduced_constraints,
             new_clusters,
+            single_roots,
             Arc::clone(&forbidden),
             &field,
+            &non_linear_round,
+            cluster_stats.as_mut(),
         );
 
         linear_extracted_non_linear = linear_extracted_non_linear + num_new_linear;
+        quadratic_extracted_groebner += quadratic.len();
+        for constraint in quadratic {
+            let c_id = constraint_storage.add_constraint(constraint.clone());
+            if let Some(worklist) = &mut worklist {
+                worklist.touch(c_id);
+            }
+            for signal in C::take_cloned_signals(&constraint) {
+                non_linear_map.entry(signal).or_insert_with(LinkedList::new).push_back(c_id);
+            }
+        }
 
         ////println!("Calculadas substituciones");
         for sub in &substitutions {
             deleted.insert(*sub.from());
         }
+        if let Some(log) = &mut substitution_log {
+            log.extend(substitutions.iter().cloned());
+        }
+        if let Some(worklist) = &mut worklist {
+            touch_substituted(worklist, &non_linear_map, &substitutions);
+        }
         
 
         let mut linear = apply_substitution_to_map_non_linear(
@@ -712,11 +1383,23 @@ pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut Constr
                 Arc::clone(&forbidden),
                 no_labels,
                 &field,
+                LinearRound {
+                    gaussian: if config.gaussian_elimination { Some(&mut elimination_summary) } else { None },
+                    fast_path: if config.fast_equalities { Some(&mut fast_path_summary) } else { None },
+                    deterministic: config.deterministic,
+                },
+                cluster_stats.as_mut(),
             );
     
             for sub in &substitutions {
                 deleted.insert(*sub.from());
             }
+            if let Some(log) = &mut substitution_log {
+                log.extend(substitutions.iter().cloned());
+            }
+            if let Some(worklist) = &mut worklist {
+                touch_substituted(worklist, &non_linear_map, &substitutions);
+            }
 
             linear = apply_substitution_to_map_non_linear(
                 constraint_storage,
@@ -749,10 +1432,47 @@ pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut Constr
             if !constraint_storage.read_constraint(possible_delete).unwrap().is_empty() {
                 total_eliminated = total_eliminated + 1;
                 constraint_storage.replace(possible_delete, C::empty());
+                if let Some(worklist) = &mut worklist {
+                    worklist.touch(possible_delete);
+                }
             }
         }
 
-        new_clusters = build_clusters_nonlinear(&constraint_storage);
+        if let Some(speculation) = speculation {
+            let cost = system_cost(constraint_storage, max_signal - deleted.len());
+            if cost.total(config.constraint_weight) > speculation.cost.total(config.constraint_weight) {
+                constraint_storage.rollback_transaction();
+                deleted = speculation.deleted;
+                if let Some(log) = &mut substitution_log {
+                    log.truncate(speculation.log_len);
+                }
+                total_eliminated = speculation.total_eliminated;
+                // the worklist is rebuilt if the rounds go on after a rejected round
+                non_linear_map = speculation.non_linear_map;
+                [
+                    constants_single_roots,
+                    linear_cancelled_factors,
+                    linear_extracted_non_linear,
+                    quadratic_extracted_groebner,
+                    linear_obtained_after_simplification,
+                ] = speculation.counters;
+                rejected_rounds += 1;
+                // the same round would be deduced again
+                apply_round_non_linear = false;
+            } else {
+                constraint_storage.commit_transaction();
+            }
+        }
+
+        new_clusters = match &mut worklist {
+            // the last round, or a rejected one whose changes are not in the storage
+            _ if !apply_round_non_linear => LinkedList::new(),
+            Some(worklist) => {
+                reanalyzed_constraints += worklist.no_affected();
+                worklist.affected_clusters(constraint_storage)
+            }
+            None => build_clusters_nonlinear(&constraint_storage),
+        };
 
 
     }
@@ -760,31 +1480,104 @@ pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut Constr
     
 
 
+
     println!("Total de constraints no lineales antes de empezar la reducción: {}",number_before_deduction);
     println!("--------------SIMPLIFICACION COMPLETADA----------------");    
     println!("Total de constraints eliminadas: {}", total_eliminated);
     println!("Total de lineales deducidas de no lineales: {}", linear_extracted_non_linear);
     println!("Total de lineales DISTINTAS deducidas de no lineales: {}", deduced_constraints.len());
     println!("Total de lineales obtenidas al simplificar: {}", linear_obtained_after_simplification);
+    if config.merge_duplicate_products {
+        println!("Total de productos duplicados fusionados: {}", merged_products);
+    }
+    if groebner.is_some() {
+        println!("Total de cuadraticas deducidas con Groebner: {}", quadratic_extracted_groebner);
+    }
+    if config.degenerate_quadratics {
+        println!("Total de lineales deducidas de cuadraticas con una sola raiz: {}", constants_single_roots);
+    }
+    if config.non_zero_cancellation {
+        println!("Expresiones no nulas: {}, lineales deducidas cancelando factores no nulos: {}", non_zero_expressions, linear_cancelled_factors);
+    }
+    if config.gaussian_elimination {
+        println!("Eliminacion gaussiana. Rango: {}, redundantes: {}, inconsistentes: {}, señales libres: {}",
+            elimination_summary.rank, elimination_summary.redundant, elimination_summary.inconsistent, elimination_summary.free_signals);
+    }
     //println!("Iteraciones de deducir lineales obtenidas de no lineales: {}", iterations_non_linear);
     if total_eliminated > 0{
         let percentage : f64  = total_eliminated as f64 / number_before_deduction as f64;
         println!("Porcentaje de mejora: {}%", percentage*(100 as f64));
     }
+    if config.fast_equalities {
+        println!("Igualdades resueltas con union-find: {}, sustituciones: {}, tiempo: {} ms, tiempo ahorrado estimado: {:.3} ms",
+            fast_path_summary.collapsed, fast_path_summary.substitutions,
+            fast_path_summary.fast_time.as_millis(), fast_path_summary.estimated_saving_ms());
+    }
+    if let Some(report) = &witness_report {
+        println!("Igualdades candidatas del testigo probadas: {}, no probadas: {}, descartadas por el limite de cada grupo: {}",
+            report.proven.len(), report.unproven.len(), witness_skipped);
+        for candidate in &report.unproven {
+            match candidate.base {
+                Some(base) => println!("    Candidata no probada: {} = {} * {} + {}", candidate.signal, candidate.factor, base, candidate.offset),
+                None => println!("    Candidata no probada: {} = {}", candidate.signal, candidate.offset),
+            }
+        }
+    }
+    if config.speculative_elimination {
+        println!("Rondas no lineales deshechas por aumentar el coste: {}", rejected_rounds);
+    }
+    if let Some(cache) = &cluster_cache {
+        let (hits, misses) = cache.hits_and_misses();
+        println!("Clusters resueltos con la cache: {}, clusters analizados: {}", hits, misses);
+    }
+    if apply_only_affected {
+        println!("Constraints modificadas en las rondas no lineales: {}", reanalyzed_constraints);
+    }
+    if let Some(stats) = &cluster_stats {
+        println!("Clusters procesados: {} en {} rondas, constraints descartadas por monomios unicos: {}",
+            stats.clusters.len(), stats.no_rounds(), stats.zero_constraints_removed);
+    }
     let dur = now.elapsed().unwrap().as_millis();
     //println!("TIME: {} ms", dur);
 
 
+    if config.boolean_detection {
+        let report = remove_duplicated_booleanity(constraint_storage, &field);
+        println!("Señales booleanas: {}, booleanidades repetidas eliminadas: {}", report.signals, report.duplicated);
+    }
+
     remove_redundant_constraints(constraint_storage, &field);
 
     let _trash = constraint_storage.extract_with(&|c| C::is_empty(c));
 
+    if config.dead_definitions {
+        let dead = remove_dead_definitions(constraint_storage, &forbidden, &field);
+        let _trash = constraint_storage.extract_with(&|c| C::is_empty(c));
+        println!("Definiciones muertas eliminadas: {}", dead.len());
+        deleted.extend(dead);
+    }
+
     //println!("Numero de constraints final: {}", constraint_storage.get_no_constraints());
 
+    let subexpressions = if config.common_subexpressions {
+        let report = extract_common_subexpressions(constraint_storage, max_signal, config.constraint_weight, &field);
+        println!("Subexpresiones comunes extraidas: {}, usos sustituidos: {}, coeficientes no nulos ahorrados: {}",
+            report.extracted.len(), report.extracted.iter().map(|e| e.uses).sum::<usize>(), report.nnz_saved);
+        report.extracted
+    } else {
+        Vec::new()
+    };
+
+    let mut no_wires = deleted.clone();
+    if !subexpressions.is_empty() {
+        // the new signals go after max_signal, so the signals without a value can not
+        // keep a wire or the wires would not follow the values of the witness
+        no_wires.extend((0..max_signal).filter(|signal| !witness.contains_key(signal)));
+    }
     let signal_map = {
         // //println!("Rebuild witness");
         let now = SystemTime::now();
-        let signal_map = rebuild_witness(max_signal, deleted.clone());
+        let signal_map = rebuild_witness(max_signal + subexpressions.len(), no_wires);
         let _dur = now.elapsed().unwrap().as_millis();
         // //println!("End of rebuild witness: {} ms", dur);
         signal_map
@@ -792,6 +1585,10 @@ pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut Constr
     let mut new_witness = witness.clone();
     println!("Veamos si funciono. Tam {}",new_witness.len());
     update_witness(& mut new_witness,deleted);
+    for subexpression in &subexpressions {
+        let value = evaluate_subexpression(&subexpression.expression, &new_witness, &field);
+        new_witness.insert(subexpression.signal, value);
+    }
     println!("¡¡¡ si funciono. Tam {}",new_witness.len());
 
     let mut signals : HashSet<usize> = HashSet::new();
@@ -802,18 +1599,9 @@ pub fn simplification(mut linear: LinkedList<C>, constraint_storage: &mut Constr
             signals.insert(e);
         }
     }
-    let mut toberemoved :  HashSet<usize>  = HashSet::new();
-    for s in new_witness.keys(){
-        if !signals.contains(s){
-            toberemoved.insert(*s);
-        }
-    }
-    for s in toberemoved{
-     //   new_witness.remove(&s);
-    }
     // //println!("NO CONSTANTS: {}", constraint_storage.no_constants());
     println!("Num signals in storage: {}, size witness: {}", signals.len(),new_witness.len());
-    (signal_map, new_witness)
+    (signal_map, new_witness, cluster_stats, substitution_log)
 }
 
 
@@ -840,3 +1628,331 @@ fn get_number_non_empty_constraints( constraint_storage : & ConstraintStorage) -
 
 //BTreemap -> en lugar de Hashmap
 //Eliminar de mi witness aquellas que estén en deleted. 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{expression, field, linear as linear_constraint, quadratic, storage};
+
+    fn groebner_round() -> NonLinearRound {
+        NonLinearRound {
+            groebner: Some(GroebnerConfig {
+                max_constraints: 8,
+                max_degree: 3,
+                time_budget: std::time::Duration::from_secs(10),
+                max_pairs: None,
+            }),
+            deterministic: true,
+            cache: None,
+        }
+    }
+
+    // x*inv = 1, x*(y - z) = 0: y = z is deduced and makes the second constraint redundant
+    fn inverse_cluster() -> ConstraintStorage {
+        storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(0, 1)]),
+            quadratic(&[(1, 1)], &[(3, 1), (4, -1)], &[]),
+        ])
+    }
+
+    #[test]
+    fn merge_products_with_swapped_factors() {
+        let field = field();
+        // (x + y)*(x + 2y) = z, (x + 2y)*(x + y) = w, (x + y)*(x + 2y) = 2*u
+        let mut constraints = storage(vec![
+            quadratic(&[(1, 1), (2, 1)], &[(1, 1), (2, 2)], &[(3, 1)]),
+            quadratic(&[(1, 1), (2, 2)], &[(1, 1), (2, 1)], &[(4, 1)]),
+            quadratic(&[(1, 1), (2, 1)], &[(1, 1), (2, 2)], &[(5, 2)]),
+        ]);
+        let (linear, merged) = merge_duplicate_products(&mut constraints, &field);
+        assert_eq!(merged, 2);
+        assert_eq!(linear.len(), 2);
+        for constraint in &linear {
+            assert!(constraint.c().contains_key(&3));
+        }
+        assert!(constraints.read_constraint(1).unwrap().is_empty());
+        assert!(constraints.read_constraint(2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn groebner_deletions_follow_their_substitutions() {
+        let field = field();
+        let cluster = inverse_cluster();
+        let (substitutions, _, delete, _, _) = non_linear_simplification(
+            &mut HashSet::new(),
+            build_clusters_nonlinear(&cluster),
+            LinkedList::new(),
+            Arc::new(HashSet::new()),
+            &field,
+            &groebner_round(),
+            None,
+        );
+        assert_eq!(substitutions.len(), 1);
+        assert!(delete.contains(&1));
+    }
+
+    #[test]
+    fn groebner_deletions_need_kept_linear_constraints() {
+        let field = field();
+        let cluster = inverse_cluster();
+        // y = z can not be substituted, so x*(y - z) = 0 has to stay
+        let forbidden: HashSet<usize> = [3, 4].iter().cloned().collect();
+        let (substitutions, _, delete, _, _) = non_linear_simplification(
+            &mut HashSet::new(),
+            build_clusters_nonlinear(&cluster),
+            LinkedList::new(),
+            Arc::new(forbidden),
+            &field,
+            &groebner_round(),
+            None,
+        );
+        assert!(substitutions.is_empty());
+        assert!(!delete.contains(&1));
+    }
+
+    fn speculative_config() -> SimplificationConfig {
+        SimplificationConfig {
+            apply_non_linear: true,
+            gaussian_elimination: false,
+            groebner_cluster_size: 8,
+            groebner_degree: 3,
+            groebner_time_budget_ms: 10_000,
+            groebner_pair_budget: 10_000,
+            merge_duplicate_products: false,
+            boolean_detection: false,
+            bit_decompositions: false,
+            dead_definitions: false,
+            cluster_stats: false,
+            slowest_clusters: 0,
+            substitution_log: false,
+            fast_equalities: false,
+            deterministic: true,
+            incremental_non_linear: true,
+            cluster_cache: false,
+            speculative_elimination: true,
+            constraint_weight: 0,
+            common_subexpressions: false,
+            fold_single_use_products: false,
+            degenerate_quadratics: false,
+            non_zero_cancellation: false,
+            range_analysis: false,
+            witness_equalities: true,
+            extra_witnesses: Vec::new(),
+        }
+    }
+
+    fn removed_definitions(constraints: Vec<C>, forbidden: &[usize]) -> (Vec<usize>, ConstraintStorage) {
+        let mut constraints = storage(constraints);
+        let forbidden: HashSet<usize> = forbidden.iter().cloned().collect();
+        let mut removed: Vec<usize> = remove_dead_definitions(&mut constraints, &forbidden, &field()).into_iter().collect();
+        removed.sort_unstable();
+        (removed, constraints)
+    }
+
+    #[test]
+    fn chain_of_dead_definitions_is_removed() {
+        // x*y = a, a*z = b: b is only defined, and then so is a
+        let (removed, constraints) = removed_definitions(
+            vec![quadratic(&[(1, 1)], &[(2, 1)], &[(3, 1)]), quadratic(&[(3, 1)], &[(4, 1)], &[(5, 1)])],
+            &[1, 2, 4],
+        );
+        assert_eq!(removed, vec![3, 5]);
+        assert!(constraints.read_constraint(0).unwrap().is_empty());
+        assert!(constraints.read_constraint(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn forbidden_definitions_are_kept() {
+        // x*y = out
+        let (removed, constraints) = removed_definitions(vec![quadratic(&[(1, 1)], &[(2, 1)], &[(3, 1)])], &[3]);
+        assert!(removed.is_empty());
+        assert!(!constraints.read_constraint(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn signals_in_a_product_are_not_dead() {
+        // x*y = a, a*z = out and w*v = w + 1, where w is in its only constraint but in A
+        let (removed, _) = removed_definitions(
+            vec![
+                quadratic(&[(1, 1)], &[(2, 1)], &[(3, 1)]),
+                quadratic(&[(3, 1)], &[(4, 1)], &[(5, 1)]),
+                quadratic(&[(6, 1)], &[(7, 1)], &[(6, 1), (0, 1)]),
+            ],
+            &[5],
+        );
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn deterministic_runs_give_the_same_system() {
+        // x*inv = 1, x*(y - z) = 0 and y + u = v, with v forbidden, many times
+        let mut constraints = Vec::new();
+        let mut witness = BTreeMap::new();
+        witness.insert(0, BigInt::from(1));
+        let mut forbidden = HashSet::new();
+        for i in 0..16 {
+            let (x, inv, y, z, u, v) = (6 * i + 1, 6 * i + 2, 6 * i + 3, 6 * i + 4, 6 * i + 5, 6 * i + 6);
+            constraints.push(quadratic(&[(x, 1)], &[(inv, 1)], &[(0, 1)]));
+            constraints.push(quadratic(&[(x, 1)], &[(y, 1), (z, -1)], &[]));
+            constraints.push(linear_constraint(&[(y, 1), (u, 1), (v, -1)]));
+            for (signal, value) in [(x, 2), (inv, 129), (y, 3 + i), (z, 3 + i), (u, 5), (v, 8 + i)] {
+                witness.insert(signal, BigInt::from(value as i64));
+            }
+            forbidden.insert(v);
+        }
+        let max_signal = 97;
+        let run = || {
+            let mut storage = storage(constraints.clone());
+            let (signal_map, witness, _, _) = simplification(
+                LinkedList::new(),
+                &mut storage,
+                forbidden.clone(),
+                max_signal,
+                max_signal,
+                field(),
+                &speculative_config(),
+                witness.clone(),
+            );
+            let system: Vec<_> = storage
+                .get_ids()
+                .into_iter()
+                .map(|c_id| storage.read_constraint(c_id).unwrap())
+                .map(|constraint| (constraint.a().clone(), constraint.b().clone(), constraint.c().clone()))
+                .collect();
+            (signal_map, witness, system)
+        };
+        assert!(run() == run());
+    }
+
+    #[test]
+    fn rounds_go_on_after_the_witness_equalities() {
+        // x*inv = 1, x*(a - b) = 0, a*c = d, b*c = 2e: a = b is only proven from the
+        // witness, and then a*c = d and a*c = 2e give d = 2e in a new round
+        let config = SimplificationConfig {
+            groebner_cluster_size: 0,
+            speculative_elimination: false,
+            ..speculative_config()
+        };
+        let mut storage = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(0, 1)]),
+            quadratic(&[(1, 1)], &[(3, 1), (4, -1)], &[]),
+            quadratic(&[(3, 1)], &[(5, 1)], &[(6, 1)]),
+            quadratic(&[(4, 1)], &[(5, 1)], &[(7, 2)]),
+        ]);
+        let forbidden: HashSet<usize> = [1, 5, 7].iter().cloned().collect();
+        let witness: BTreeMap<usize, BigInt> =
+            [1, 2, 129, 3, 3, 5, 15, 136].iter().enumerate().map(|(s, v)| (s, BigInt::from(*v))).collect();
+        let (signal_map, _, _, _) = simplification(LinkedList::new(), &mut storage, forbidden, 8, 8, field(), &config, witness);
+        assert!(!signal_map.contains_key(&4));
+        assert!(!signal_map.contains_key(&6));
+        let remaining = storage.get_ids().into_iter().filter(|c_id| !storage.read_constraint(*c_id).unwrap().is_empty()).count();
+        assert_eq!(remaining, 2);
+    }
+
+    // x*y = out0, x*y = out1: out0 = out1 can not be substituted and has to stay
+    #[test]
+    fn merged_product_of_two_outputs_keeps_their_equality() {
+        let field = field();
+        let config = SimplificationConfig {
+            apply_non_linear: false,
+            groebner_cluster_size: 0,
+            merge_duplicate_products: true,
+            speculative_elimination: false,
+            witness_equalities: false,
+            ..speculative_config()
+        };
+        let mut storage = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(3, 1)]),
+            quadratic(&[(1, 1)], &[(2, 1)], &[(4, 1)]),
+        ]);
+        let forbidden: HashSet<usize> = [3, 4].iter().cloned().collect();
+        let witness: BTreeMap<usize, BigInt> = [1, 2, 3, 6, 6].iter().enumerate().map(|(s, v)| (s, BigInt::from(*v))).collect();
+        simplification(LinkedList::new(), &mut storage, forbidden, 5, 5, field, &config, witness);
+        let remaining: Vec<C> = storage.get_ids().into_iter().map(|c_id| storage.read_constraint(c_id).unwrap()).filter(|c| !c.is_empty()).collect();
+        assert_eq!(remaining.len(), 2);
+        assert!(remaining.iter().any(|c| C::is_linear(c) && c.c().contains_key(&3) && c.c().contains_key(&4)));
+    }
+
+    #[test]
+    fn rejected_round_restores_the_signal_map() {
+        let field = field();
+        // x*y = 0, x*z = t, x*(y + w) = 1, x*w2 = 1: y = 0 and w = w2 are deduced and Groebner
+        // adds x*w = 1. a*ainv = 1, a*(b - c - d - e) = 0 and the products e*g = h: e = b - c - d
+        // fills in every product, so the round is rejected with constraint weight 0
+        let mut constraints = vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[]),
+            quadratic(&[(1, 1)], &[(3, 1)], &[(5, 1)]),
+            quadratic(&[(1, 1)], &[(2, 1), (4, 1)], &[(0, 1)]),
+            quadratic(&[(1, 1)], &[(6, 1)], &[(0, 1)]),
+            quadratic(&[(7, 1)], &[(8, 1)], &[(0, 1)]),
+            quadratic(&[(7, 1)], &[(9, 1), (10, -1), (11, -1), (12, -1)], &[]),
+        ];
+        let values = [1, 2, 0, 6, 129, 12, 129, 3, 86, 10, 1, 2, 7];
+        let mut witness: BTreeMap<usize, BigInt> = values.iter().enumerate().map(|(s, v)| (s, BigInt::from(*v))).collect();
+        for i in 0..10 {
+            let (g, h) = (13 + 2 * i, 14 + 2 * i);
+            constraints.push(quadratic(&[(12, 1)], &[(g, 1)], &[(h, 1)]));
+            witness.insert(g, BigInt::from(30 + i as i64));
+            witness.insert(h, BigInt::from((7 * (30 + i as i64)) % 257));
+        }
+        let max_signal = 33;
+        let mut storage = storage(constraints);
+        let forbidden: HashSet<usize> = [6, 9, 10, 11].iter().cloned().collect();
+        // w = w2 is proven again from the witness after the rejected round
+        let (signal_map, _, _, _) = simplification(
+            LinkedList::new(),
+            &mut storage,
+            forbidden,
+            max_signal,
+            max_signal,
+            field,
+            &speculative_config(),
+            witness,
+        );
+        assert!(!signal_map.contains_key(&4));
+        for c_id in storage.get_ids() {
+            let constraint = storage.read_constraint(c_id).unwrap();
+            for signal in C::take_cloned_signals(&constraint) {
+                assert!(signal_map.contains_key(&signal));
+            }
+        }
+    }
+
+    // x*y = c, c - d - 2e = 0: c is only used to define d + 2e, so x*y = d + 2e
+    #[test]
+    fn single_use_product_is_folded() {
+        let field = field();
+        let mut constraints = storage(vec![quadratic(&[(1, 1)], &[(2, 1)], &[(3, 1)])]);
+        let mut map = build_non_linear_signal_map(&constraints);
+        let mut linear = LinkedList::new();
+        linear.push_back(linear_constraint(&[(3, 1), (4, -1), (5, -2)]));
+        let (remaining, folded) = fold_single_use_products(linear, &mut constraints, &mut map, &HashSet::new(), &field);
+        assert!(remaining.is_empty());
+        assert_eq!(folded.len(), 1);
+        assert_eq!(*folded.front().unwrap().from(), 3);
+        assert_eq!(constraints.read_constraint(0).unwrap().c(), &expression(&[(4, 1), (5, 2)]));
+        assert!(!map.contains_key(&3));
+        assert!(map[&4].contains(&0) && map[&5].contains(&0));
+    }
+
+    #[test]
+    fn products_used_twice_or_forbidden_are_not_folded() {
+        let field = field();
+        let mut constraints = storage(vec![quadratic(&[(1, 1)], &[(2, 1)], &[(3, 1)])]);
+        let mut map = build_non_linear_signal_map(&constraints);
+        let mut linear = LinkedList::new();
+        linear.push_back(linear_constraint(&[(3, 1), (4, -1)]));
+        linear.push_back(linear_constraint(&[(3, 1), (5, -1)]));
+        let (remaining, folded) = fold_single_use_products(linear, &mut constraints, &mut map, &HashSet::new(), &field);
+        assert_eq!(remaining.len(), 2);
+        assert!(folded.is_empty());
+
+        let mut linear = LinkedList::new();
+        linear.push_back(linear_constraint(&[(3, 1), (4, -1)]));
+        let forbidden: HashSet<usize> = [3].iter().cloned().collect();
+        let (remaining, folded) = fold_single_use_products(linear, &mut constraints, &mut map, &forbidden, &field);
+        assert_eq!(remaining.len(), 1);
+        assert!(folded.is_empty());
+        assert_eq!(constraints.read_constraint(0).unwrap().c(), &expression(&[(3, 1)]));
+    }
+}
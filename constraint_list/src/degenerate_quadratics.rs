@@ -0,0 +1,198 @@
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::{One, Zero};
+use std::collections::{BTreeMap, HashMap, LinkedList};
+use super::{ConstraintStorage, C};
+
+/*
+    Deduction of constants from the quadratic constraints whose sides are all affine in
+    the same linear form L (x*x = 0, (x - k)*(x - k) = 0, (x - y)*(2x - 2y + 1) = 3...).
+    Such a constraint is p*L^2 + q*L + r = 0, so L can only take the roots of the
+    polynomial: a single one when the discriminant is 0, two when it is a non zero
+    square (computed with Tonelli-Shanks) and none otherwise. The roots of all the
+    constraints of the same form are intersected, and when a single value is left the
+    linear constraint L = value is deduced.
+*/
+
+// Linear form without constant term, its smallest signal has coefficient 1
+pub type Form = Vec<(usize, BigInt)>;
+
+// expression = factor * form + constant
+struct Affine {
+    form: Form,
+    factor: BigInt,
+    constant: BigInt,
+}
+
+fn split(expression: &HashMap<usize, BigInt>, field: &BigInt) -> Affine {
+    let constant_signal = C::constant_coefficient();
+    let zero = BigInt::zero();
+    let constant = expression
+        .get(&constant_signal)
+        .map_or_else(BigInt::zero, |value| modular_arithmetic::add(value, &zero, field));
+    let mut form: Form = expression
+        .iter()
+        .filter(|(signal, _)| **signal != constant_signal)
+        .map(|(signal, value)| (*signal, modular_arithmetic::add(value, &zero, field)))
+        .filter(|(_, value)| !value.is_zero())
+        .collect();
+    form.sort();
+    let factor = match form.first() {
+        Some((_, factor)) => factor.clone(),
+        None => BigInt::zero(),
+    };
+    if !factor.is_zero() {
+        let inverse = modular_arithmetic::div(&BigInt::one(), &factor, field).unwrap();
+        for (_, value) in form.iter_mut() {
+            *value = modular_arithmetic::mul(value, &inverse, field);
+        }
+    }
+    Affine { form, factor, constant }
+}
+
+// Roots of p*x^2 + q*x + r with p != 0, sorted
+fn roots(p: &BigInt, q: &BigInt, r: &BigInt, field: &BigInt) -> Option<Vec<BigInt>> {
+    let four = BigInt::from(4);
+    let discriminant = modular_arithmetic::sub(
+        &modular_arithmetic::mul(q, q, field),
+        &modular_arithmetic::mul(&four, &modular_arithmetic::mul(p, r, field), field),
+        field,
+    );
+    let two_p = modular_arithmetic::mul(&BigInt::from(2), p, field);
+    let minus_q = modular_arithmetic::prefix_sub(q, field);
+    let mut values = Vec::new();
+    match modular_arithmetic::legendre_symbol(&discriminant, field) {
+        0 => values.push(modular_arithmetic::div(&minus_q, &two_p, field).ok()?),
+        1 => {
+            let root = modular_arithmetic::sqrt(&discriminant, field)?;
+            for numerator in [
+                modular_arithmetic::add(&minus_q, &root, field),
+                modular_arithmetic::sub(&minus_q, &root, field),
+            ] {
+                values.push(modular_arithmetic::div(&numerator, &two_p, field).ok()?);
+            }
+            values.sort();
+            values.dedup();
+        }
+        _ => {}
+    }
+    Some(values)
+}
+
+// The form of the constraint and the values it allows for it
+pub fn allowed_values(constraint: &C, field: &BigInt) -> Option<(Form, Vec<BigInt>)> {
+    let a = split(constraint.a(), field);
+    let b = split(constraint.b(), field);
+    let c = split(constraint.c(), field);
+    if a.form.is_empty() || a.form != b.form || (!c.form.is_empty() && c.form != a.form) {
+        return None;
+    }
+    // (fa*L + ka)*(fb*L + kb) - (fc*L + kc)
+    let p = modular_arithmetic::mul(&a.factor, &b.factor, field);
+    let q = modular_arithmetic::sub(
+        &modular_arithmetic::add(
+            &modular_arithmetic::mul(&a.factor, &b.constant, field),
+            &modular_arithmetic::mul(&a.constant, &b.factor, field),
+            field,
+        ),
+        &c.factor,
+        field,
+    );
+    let r = modular_arithmetic::sub(&modular_arithmetic::mul(&a.constant, &b.constant, field), &c.constant, field);
+    if p.is_zero() {
+        return None;
+    }
+    Some((a.form, roots(&p, &q, &r, field)?))
+}
+
+// The linear constraints L = value of the forms that can only take one value. The
+// forms without any possible value (an unsatisfiable system) are left as they are.
+pub fn deduce_single_roots(storage: &ConstraintStorage, field: &BigInt) -> LinkedList<C> {
+    let mut forms: BTreeMap<Form, Vec<BigInt>> = BTreeMap::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if C::is_empty(&constraint) || C::is_linear(&constraint) {
+            continue;
+        }
+        if let Some((form, values)) = allowed_values(&constraint, field) {
+            match forms.get_mut(&form) {
+                Some(current) => current.retain(|value| values.contains(value)),
+                None => {
+                    forms.insert(form, values);
+                }
+            }
+        }
+    }
+    let mut linear = LinkedList::new();
+    for (form, values) in forms {
+        if values.len() != 1 {
+            continue;
+        }
+        let mut expression: HashMap<usize, BigInt> = form.into_iter().collect();
+        if !values[0].is_zero() {
+            expression.insert(C::constant_coefficient(), modular_arithmetic::prefix_sub(&values[0], field));
+        }
+        linear.push_back(C::new(HashMap::new(), HashMap::new(), expression));
+    }
+    linear
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{expression, field, quadratic, storage};
+
+    fn values(constraint: &C) -> Vec<BigInt> {
+        allowed_values(constraint, &field()).unwrap().1
+    }
+
+    fn deduced(constraints: Vec<C>) -> Vec<HashMap<usize, BigInt>> {
+        deduce_single_roots(&storage(constraints), &field()).iter().map(|constraint| constraint.c().clone()).collect()
+    }
+
+    #[test]
+    fn square_equal_to_zero() {
+        // x*x = 0 and 3x*x = 0
+        assert_eq!(deduced(vec![quadratic(&[(1, 1)], &[(1, 1)], &[])]), vec![expression(&[(1, 1)])]);
+        assert_eq!(deduced(vec![quadratic(&[(1, 3)], &[(1, 1)], &[])]), vec![expression(&[(1, 1)])]);
+    }
+
+    #[test]
+    fn shifted_square_equal_to_zero() {
+        // (x - 5)*(x - 5) = 0 and (x - y)*(2x - 2y) = 0
+        let shifted = quadratic(&[(1, 1), (0, -5)], &[(1, 1), (0, -5)], &[]);
+        assert_eq!(values(&shifted), vec![BigInt::from(5)]);
+        assert_eq!(deduced(vec![shifted]), vec![expression(&[(1, 1), (0, -5)])]);
+        let difference = quadratic(&[(1, 1), (2, -1)], &[(1, 2), (2, -2)], &[]);
+        assert_eq!(deduced(vec![difference]), vec![expression(&[(1, 1), (2, -1)])]);
+    }
+
+    #[test]
+    fn non_square_discriminant_has_no_root() {
+        // x*x = 3, 3 is not a square mod 257
+        let constraint = quadratic(&[(1, 1)], &[(1, 1)], &[(0, 3)]);
+        assert!(values(&constraint).is_empty());
+        assert!(deduced(vec![constraint]).is_empty());
+    }
+
+    #[test]
+    fn two_roots_are_not_a_constant() {
+        // x*(x - 1) = 0 and x*x = 4
+        let boolean = quadratic(&[(1, 1)], &[(1, 1), (0, -1)], &[]);
+        assert_eq!(values(&boolean), vec![BigInt::from(0), BigInt::from(1)]);
+        assert!(deduced(vec![boolean.clone()]).is_empty());
+        let square = quadratic(&[(1, 1)], &[(1, 1)], &[(0, 4)]);
+        assert_eq!(values(&square), vec![BigInt::from(2), BigInt::from(255)]);
+        assert!(deduced(vec![square]).is_empty());
+        // x*(x - 2) = 0 leaves 0 as the only common root
+        let other = quadratic(&[(1, 1)], &[(1, 1), (0, -2)], &[]);
+        assert_eq!(deduced(vec![boolean, other]), vec![expression(&[(1, 1)])]);
+    }
+
+    #[test]
+    fn different_forms_are_not_degenerate() {
+        // x*y = 0 and x*x = y
+        assert!(allowed_values(&quadratic(&[(1, 1)], &[(2, 1)], &[]), &field()).is_none());
+        assert!(allowed_values(&quadratic(&[(1, 1)], &[(1, 1)], &[(2, 1)]), &field()).is_none());
+    }
+}
@@ -0,0 +1,192 @@
+use circom_algebra::algebra::ArithmeticExpression;
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::Zero;
+use std::collections::{HashMap, HashSet, LinkedList};
+use super::{C, S};
+
+/*
+    Fast path for the linear constraints of the form x = y and x = k. They are
+    collapsed with a union-find in which the representative of a class is its forbidden
+    signal (if any) or its smallest signal. Every other signal of the class is
+    substituted by the constant of the class or, when there is none or the
+    representative is forbidden, by the representative. The constraints
+    that can not be collapsed (two forbidden signals, two different constants, or
+    any other kind of linear constraint) are returned rewritten for the general path.
+*/
+
+pub struct FastEqualities {
+    pub substitutions: LinkedList<S>,
+    pub remaining: LinkedList<C>,
+    // number of constraints solved by the union-find
+    pub collapsed: usize,
+}
+
+struct EqualityClasses<'a> {
+    parent: HashMap<usize, usize>,
+    // constant of the class of each representative
+    value: HashMap<usize, BigInt>,
+    forbidden: &'a HashSet<usize>,
+}
+
+impl<'a> EqualityClasses<'a> {
+    fn find(&mut self, signal: usize) -> usize {
+        let parent = *self.parent.entry(signal).or_insert(signal);
+        if parent == signal {
+            return signal;
+        }
+        let representative = self.find(parent);
+        self.parent.insert(signal, representative);
+        representative
+    }
+
+    // false when the classes can not be merged
+    fn union(&mut self, x: usize, y: usize) -> bool {
+        let (x, y) = (self.find(x), self.find(y));
+        if x == y {
+            return true;
+        }
+        let (x_forbidden, y_forbidden) = (self.forbidden.contains(&x), self.forbidden.contains(&y));
+        if x_forbidden && y_forbidden {
+            return false;
+        }
+        if let (Some(vx), Some(vy)) = (self.value.get(&x), self.value.get(&y)) {
+            if vx != vy {
+                return false;
+            }
+        }
+        let (representative, other) = if x_forbidden || (!y_forbidden && x < y) { (x, y) } else { (y, x) };
+        self.parent.insert(other, representative);
+        if let Some(value) = self.value.remove(&other) {
+            self.value.insert(representative, value);
+        }
+        true
+    }
+
+    // false when the class already has a different constant
+    fn assign(&mut self, x: usize, value: BigInt) -> bool {
+        let x = self.find(x);
+        match self.value.get(&x) {
+            Some(current) => *current == value,
+            None => {
+                self.value.insert(x, value);
+                true
+            }
+        }
+    }
+}
+
+pub fn collapse_equalities(linear: LinkedList<C>, forbidden: &HashSet<usize>, field: &BigInt) -> FastEqualities {
+    let constant = C::constant_coefficient();
+    let mut classes = EqualityClasses { parent: HashMap::new(), value: HashMap::new(), forbidden };
+    let mut remaining = LinkedList::new();
+    let mut collapsed = 0;
+    for constraint in linear {
+        let solved = if constraint.is_equality(field) {
+            let signals: Vec<usize> = constraint.c().keys().cloned().collect();
+            classes.union(signals[0], signals[1])
+        } else if constraint.is_constant_equality() && !constraint.c().get(&constant).unwrap().is_zero() {
+            let (signal, coefficient) = constraint.c().iter().find(|(s, _)| **s != constant).unwrap();
+            let independent = constraint.c().get(&constant).unwrap();
+            let value = modular_arithmetic::div(independent, coefficient, field).unwrap();
+            classes.assign(*signal, modular_arithmetic::prefix_sub(&value, field))
+        } else {
+            false
+        };
+        if solved {
+            collapsed += 1;
+        } else {
+            remaining.push_back(constraint);
+        }
+    }
+
+    let mut signals: Vec<usize> = classes.parent.keys().cloned().collect();
+    signals.sort_unstable();
+    let mut substitutions = HashMap::new();
+    for signal in signals {
+        let representative = classes.find(signal);
+        let to = match classes.value.get(&representative) {
+            Some(value) if !forbidden.contains(&representative) => ArithmeticExpression::Number { value: value.clone() },
+            Some(value) if signal == representative => {
+                // the forbidden representative keeps its value through a constraint
+                let mut c = HashMap::new();
+                c.insert(signal, BigInt::from(1));
+                c.insert(constant, modular_arithmetic::prefix_sub(value, field));
+                remaining.push_back(C::new(HashMap::new(), HashMap::new(), c));
+                continue;
+            }
+            _ if signal != representative => ArithmeticExpression::Signal { symbol: representative },
+            _ => continue,
+        };
+        substitutions.insert(signal, S::new(signal, to).unwrap());
+    }
+
+    let mut rewritten = LinkedList::new();
+    for mut constraint in remaining {
+        for signal in C::take_cloned_signals(&constraint) {
+            if let Some(substitution) = substitutions.get(&signal) {
+                C::apply_substitution(&mut constraint, substitution, field);
+            }
+        }
+        rewritten.push_back(constraint);
+    }
+    let mut ordered: Vec<(usize, S)> = substitutions.into_iter().collect();
+    ordered.sort_by_key(|(signal, _)| *signal);
+    FastEqualities {
+        substitutions: ordered.into_iter().map(|(_, substitution)| substitution).collect(),
+        remaining: rewritten,
+        collapsed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{expression, field, linear};
+
+    #[test]
+    fn classes_take_their_constant_or_forbidden_representative() {
+        let field = field();
+        let forbidden: HashSet<usize> = [4, 5].iter().cloned().collect();
+        // x1 = x2, x2 = x3, x3 = 5, x4 = x6, x4 = x5 (both forbidden), x1 + x4 + x7 = 0
+        let constraints: LinkedList<C> = vec![
+            linear(&[(1, 1), (2, -1)]),
+            linear(&[(2, 1), (3, -1)]),
+            linear(&[(3, 1), (0, -5)]),
+            linear(&[(4, 1), (6, -1)]),
+            linear(&[(4, 1), (5, -1)]),
+            linear(&[(1, 1), (4, 1), (7, 1)]),
+        ]
+        .into_iter()
+        .collect();
+        let result = collapse_equalities(constraints, &forbidden, &field);
+        assert_eq!(result.collapsed, 4);
+        let substitutions: Vec<(usize, HashMap<usize, BigInt>)> =
+            result.substitutions.iter().map(|s| (*s.from(), s.to().clone())).collect();
+        assert_eq!(substitutions, vec![
+            (1, expression(&[(0, 5)])),
+            (2, expression(&[(0, 5)])),
+            (3, expression(&[(0, 5)])),
+            (6, expression(&[(4, 1)])),
+        ]);
+        let remaining: Vec<HashMap<usize, BigInt>> = result.remaining.iter().map(|c| c.c().clone()).collect();
+        assert_eq!(remaining, vec![expression(&[(4, 1), (5, -1)]), expression(&[(0, 5), (4, 1), (7, 1)])]);
+    }
+
+    #[test]
+    fn forbidden_representative_keeps_its_constant() {
+        let field = field();
+        let forbidden: HashSet<usize> = [1].iter().cloned().collect();
+        // x1 = x2, x2 = 3, x2 = 4
+        let constraints: LinkedList<C> =
+            vec![linear(&[(1, 1), (2, -1)]), linear(&[(2, 1), (0, -3)]), linear(&[(2, 1), (0, -4)])].into_iter().collect();
+        let result = collapse_equalities(constraints, &forbidden, &field);
+        assert_eq!(result.collapsed, 2);
+        let substitutions: Vec<(usize, HashMap<usize, BigInt>)> =
+            result.substitutions.iter().map(|s| (*s.from(), s.to().clone())).collect();
+        assert_eq!(substitutions, vec![(2, expression(&[(1, 1)]))]);
+        // the contradiction stays for the general path, x1 = 3 keeps the value of the class
+        let remaining: Vec<HashMap<usize, BigInt>> = result.remaining.iter().map(|c| c.c().clone()).collect();
+        assert_eq!(remaining, vec![expression(&[(1, 1), (0, -4)]), expression(&[(1, 1), (0, -3)])]);
+    }
+}
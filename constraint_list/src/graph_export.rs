@@ -0,0 +1,192 @@
+use crate::constraint_simplification::{build_clusters_by, build_clusters_nonlinear};
+use crate::non_linear_simplification::{obtain_non_linear_clusters, NonLinearClustersConfig};
+use crate::signal_table::{signal_name, signal_role, SignalTable};
+use circom_algebra::num_bigint::BigInt;
+use constraint_writers::graph_writer::{Graph, GraphEdge, GraphNode};
+use std::collections::{BTreeSet, HashMap, LinkedList};
+use super::{ConstraintStorage, C};
+
+/*
+    Bipartite graph between signals and constraints. When a kind of cluster is
+    given, only the constraints that belong to a cluster of that kind are exported
+    and every constraint node gets the id of its cluster as an attribute.
+*/
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ClusterKind {
+    // clusters of linear constraints sharing signals (build_clusters)
+    Linear,
+    // clusters of non-linear constraints sharing monomials (build_clusters_nonlinear)
+    Monomial,
+    // minimal clusters analyzed by the non-linear deduction (obtain_non_linear_clusters)
+    NonLinear,
+}
+
+#[derive(Default)]
+pub struct GraphFilter {
+    // only export the constraints of this cluster
+    pub cluster: Option<usize>,
+    // only export the signals whose name starts with this prefix
+    pub signal_prefix: Option<String>,
+}
+
+// Clusters as lists of ids of constraints of storage
+pub fn compute_clusters(storage: &ConstraintStorage, field: &BigInt, kind: ClusterKind) -> Vec<Vec<usize>> {
+    let mut linear = LinkedList::new();
+    let mut non_linear = ConstraintStorage::new();
+    let mut no_vars = 0;
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if constraint.is_empty() {
+            continue;
+        }
+        if let Some(max) = C::take_cloned_signals(&constraint).into_iter().max() {
+            no_vars = std::cmp::max(no_vars, max + 1);
+        }
+        if C::is_linear(&constraint) {
+            linear.push_back((c_id, constraint));
+        } else {
+            non_linear.add_constraint_with_prev_id(constraint, c_id);
+        }
+    }
+    let ids_of = |cluster: &ConstraintStorage| -> Vec<usize> {
+        cluster.get_ids().into_iter().map(|id| cluster.read_constraint_prev_id(id).unwrap()).collect()
+    };
+    match kind {
+        ClusterKind::Linear => {
+            let signals = |element: &(usize, C)| C::take_cloned_signals(&element.1);
+            build_clusters_by(linear, no_vars, &signals)
+                .into_iter()
+                .map(|cluster| cluster.constraints.into_iter().map(|(c_id, _)| c_id).collect())
+                .collect()
+        }
+        ClusterKind::Monomial => build_clusters_nonlinear(&non_linear).iter().map(ids_of).collect(),
+        ClusterKind::NonLinear => {
+            let mut clusters = Vec::new();
+            for storage in build_clusters_nonlinear(&non_linear) {
+                let config = NonLinearClustersConfig { field: field.clone(), storage, deterministic: true };
+                for cluster in obtain_non_linear_clusters(config) {
+                    clusters.push(ids_of(&cluster));
+                }
+            }
+            clusters
+        }
+    }
+}
+
+pub fn build_graph(
+    storage: &ConstraintStorage,
+    table: &SignalTable,
+    field: &BigInt,
+    kind: Option<ClusterKind>,
+    filter: &GraphFilter,
+) -> Graph {
+    let mut constraint_to_cluster = HashMap::new();
+    let selected: Vec<usize> = match kind {
+        Some(kind) => {
+            let clusters = compute_clusters(storage, field, kind);
+            for (cluster_id, cluster) in clusters.iter().enumerate() {
+                if filter.cluster.is_none_or(|selected| selected == cluster_id) {
+                    for c_id in cluster {
+                        constraint_to_cluster.insert(*c_id, cluster_id);
+                    }
+                }
+            }
+            let mut selected: Vec<usize> = constraint_to_cluster.keys().cloned().collect();
+            selected.sort_unstable();
+            selected
+        }
+        None => storage.get_ids(),
+    };
+
+    let keep_signal = |signal: usize| match &filter.signal_prefix {
+        Some(prefix) => signal_name(table, signal).starts_with(prefix.as_str()),
+        None => true,
+    };
+    let mut graph = Graph::new("constraints");
+    let mut signals = BTreeSet::new();
+    for c_id in selected {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if constraint.is_empty() {
+            continue;
+        }
+        let constraint_signals: Vec<usize> =
+            C::take_cloned_signals(&constraint).into_iter().filter(|s| keep_signal(*s)).collect();
+        if constraint_signals.is_empty() {
+            continue;
+        }
+        let shape = if C::is_linear(&constraint) { "linear" } else { "quadratic" };
+        let mut attributes = vec![
+            ("type".to_string(), "constraint".to_string()),
+            ("label".to_string(), format!("c{}", c_id)),
+            ("kind".to_string(), shape.to_string()),
+        ];
+        if let Some(cluster_id) = constraint_to_cluster.get(&c_id) {
+            attributes.push(("cluster".to_string(), cluster_id.to_string()));
+        }
+        graph.nodes.push(GraphNode { id: format!("c{}", c_id), attributes });
+        for signal in constraint_signals {
+            graph.edges.push(GraphEdge { source: format!("s{}", signal), target: format!("c{}", c_id) });
+            signals.insert(signal);
+        }
+    }
+    for signal in signals {
+        let attributes = vec![
+            ("type".to_string(), "signal".to_string()),
+            ("label".to_string(), signal_name(table, signal)),
+            ("role".to_string(), signal_role(table, signal).as_str().to_string()),
+        ];
+        graph.nodes.push(GraphNode { id: format!("s{}", signal), attributes });
+    }
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal_table::{SignalInfo, SignalRole};
+    use crate::test_utils::{field, linear, quadratic, storage};
+
+    // x + y = 0, y = z, w = v, a*b = c, a*b = d
+    fn circuit() -> ConstraintStorage {
+        storage(vec![
+            linear(&[(1, 1), (2, 1)]),
+            linear(&[(2, 1), (3, -1)]),
+            linear(&[(4, 1), (5, -1)]),
+            quadratic(&[(6, 1)], &[(7, 1)], &[(8, 1)]),
+            quadratic(&[(6, 1)], &[(7, 1)], &[(9, 1)]),
+        ])
+    }
+
+    fn attribute<'a>(node: &'a GraphNode, key: &str) -> Option<&'a str> {
+        node.attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn clusters_of_each_kind() {
+        let field = field();
+        let mut linear = compute_clusters(&circuit(), &field, ClusterKind::Linear);
+        for cluster in &mut linear {
+            cluster.sort_unstable();
+        }
+        linear.sort();
+        assert_eq!(linear, vec![vec![0, 1], vec![2]]);
+        assert_eq!(compute_clusters(&circuit(), &field, ClusterKind::Monomial), vec![vec![3, 4]]);
+    }
+
+    #[test]
+    fn graph_of_a_cluster_with_a_signal_filter() {
+        let field = field();
+        // c and d are signals of a subcomponent
+        let name = |s: usize| if s < 8 { format!("main.s{}", s) } else { format!("aux.s{}", s) };
+        let table: SignalTable = (0..10).map(|s| (s, SignalInfo { name: name(s), role: SignalRole::Private })).collect();
+        let filter = GraphFilter { cluster: Some(0), signal_prefix: Some("main.".to_string()) };
+        let graph = build_graph(&circuit(), &table, &field, Some(ClusterKind::Monomial), &filter);
+        let ids: Vec<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+        assert_eq!(ids, vec!["c3", "c4", "s6", "s7"]);
+        assert_eq!(attribute(&graph.nodes[0], "cluster"), Some("0"));
+        assert_eq!(attribute(&graph.nodes[0], "kind"), Some("quadratic"));
+        assert_eq!(attribute(&graph.nodes[2], "label"), Some("main.s6"));
+        assert_eq!(graph.edges.len(), 4);
+    }
+}
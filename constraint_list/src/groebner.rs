@@ -0,0 +1,422 @@
+use circom_algebra::algebra::{apply_substitution, Constraint, HashConstraint};
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::Zero;
+use std::collections::{BTreeMap, HashMap, HashSet, LinkedList};
+use std::time::{Duration, Instant};
+use super::{ConstraintStorage, C, S};
+
+/*
+    Degree bounded Buchberger algorithm over F_p. The constraints of a cluster are
+    seen as polynomials A*B - C; S-polynomials whose lcm exceeds max_degree are not
+    computed, so the result is not a Groebner basis in general, but every polynomial
+    it contains belongs to the ideal of the cluster. The polynomials of degree one
+    are new linear constraints; the ones of degree two with a single quadratic
+    monomial can replace the constraints of the cluster they make redundant.
+*/
+
+#[derive(Clone)]
+pub struct GroebnerConfig {
+    pub max_constraints: usize,
+    pub max_degree: usize,
+    pub time_budget: Duration,
+    // when given, the number of S-pairs computed bounds the basis instead of the time
+    // budget, so the deductions do not depend on the load of the machine
+    pub max_pairs: Option<usize>,
+}
+
+pub struct GroebnerDeduction {
+    pub linear: LinkedList<C>,
+    pub quadratic: LinkedList<C>,
+    pub to_delete: LinkedList<usize>,
+}
+
+// Signals are kept in decreasing order, so the derived order is graded
+// lexicographic with the greatest signals first.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Monomial {
+    degree: usize,
+    signals: Vec<usize>,
+}
+
+impl Monomial {
+    fn new(mut signals: Vec<usize>) -> Monomial {
+        signals.sort_unstable_by(|a, b| b.cmp(a));
+        Monomial { degree: signals.len(), signals }
+    }
+
+    fn one() -> Monomial {
+        Monomial::new(Vec::new())
+    }
+
+    fn mul(&self, other: &Monomial) -> Monomial {
+        let mut signals = self.signals.clone();
+        signals.extend_from_slice(&other.signals);
+        Monomial::new(signals)
+    }
+
+    fn from_exponents(exponents: BTreeMap<usize, usize>) -> Monomial {
+        let mut signals = Vec::new();
+        for (signal, exponent) in exponents {
+            signals.extend(std::iter::repeat_n(signal, exponent));
+        }
+        Monomial::new(signals)
+    }
+
+    fn exponents(&self) -> BTreeMap<usize, usize> {
+        let mut exponents = BTreeMap::new();
+        for signal in &self.signals {
+            *exponents.entry(*signal).or_insert(0) += 1;
+        }
+        exponents
+    }
+
+    fn divides(&self, other: &Monomial) -> bool {
+        let other_exponents = other.exponents();
+        self.exponents().iter().all(|(s, e)| other_exponents.get(s).is_some_and(|o| o >= e))
+    }
+
+    // self / other, assuming other divides self
+    fn quotient(&self, other: &Monomial) -> Monomial {
+        let mut exponents = self.exponents();
+        for signal in &other.signals {
+            *exponents.get_mut(signal).unwrap() -= 1;
+        }
+        Monomial::from_exponents(exponents)
+    }
+
+    fn lcm(&self, other: &Monomial) -> Monomial {
+        let mut exponents = self.exponents();
+        for (signal, exponent) in other.exponents() {
+            let current = exponents.entry(signal).or_insert(0);
+            *current = std::cmp::max(*current, exponent);
+        }
+        Monomial::from_exponents(exponents)
+    }
+
+    fn coprime(&self, other: &Monomial) -> bool {
+        let exponents = self.exponents();
+        other.signals.iter().all(|s| !exponents.contains_key(s))
+    }
+}
+
+type Polynomial = BTreeMap<Monomial, BigInt>;
+
+fn leading(p: &Polynomial) -> Option<(&Monomial, &BigInt)> {
+    p.iter().next_back()
+}
+
+fn degree(p: &Polynomial) -> usize {
+    leading(p).map_or(0, |(m, _)| m.degree)
+}
+
+fn add_term(p: &mut Polynomial, monomial: Monomial, value: &BigInt, field: &BigInt) {
+    let current = p.get(&monomial).cloned().unwrap_or_else(BigInt::zero);
+    let new_value = modular_arithmetic::add(&current, value, field);
+    if new_value.is_zero() {
+        p.remove(&monomial);
+    } else {
+        p.insert(monomial, new_value);
+    }
+}
+
+// p - coefficient * monomial * q
+fn sub_scaled(p: &mut Polynomial, coefficient: &BigInt, monomial: &Monomial, q: &Polynomial, field: &BigInt) {
+    for (m, v) in q {
+        let value = modular_arithmetic::mul(coefficient, v, field);
+        add_term(p, m.mul(monomial), &modular_arithmetic::prefix_sub(&value, field), field);
+    }
+}
+
+fn make_monic(p: &mut Polynomial, field: &BigInt) {
+    if let Some((_, lc)) = leading(p) {
+        let lc = lc.clone();
+        for value in p.values_mut() {
+            *value = modular_arithmetic::div(value, &lc, field).unwrap();
+        }
+    }
+}
+
+fn from_constraint(constraint: &C, field: &BigInt) -> Polynomial {
+    let constant = C::constant_coefficient();
+    let as_monomial = |s: usize| if s == constant { Monomial::one() } else { Monomial::new(vec![s]) };
+    let mut p = Polynomial::new();
+    for (signal_a, coef_a) in constraint.a() {
+        for (signal_b, coef_b) in constraint.b() {
+            let value = modular_arithmetic::mul(coef_a, coef_b, field);
+            add_term(&mut p, as_monomial(*signal_a).mul(&as_monomial(*signal_b)), &value, field);
+        }
+    }
+    for (signal, value) in constraint.c() {
+        add_term(&mut p, as_monomial(*signal), &modular_arithmetic::prefix_sub(value, field), field);
+    }
+    p
+}
+
+// Only polynomials of degree at most one or with a single quadratic monomial
+// can be written back as constraints
+fn into_constraint(p: &Polynomial, field: &BigInt) -> Option<C> {
+    let constant = C::constant_coefficient();
+    let mut a = HashMap::new();
+    let mut b = HashMap::new();
+    let mut c = HashMap::new();
+    for (monomial, value) in p {
+        match monomial.degree {
+            0 => {
+                c.insert(constant, modular_arithmetic::prefix_sub(value, field));
+            }
+            1 => {
+                c.insert(monomial.signals[0], modular_arithmetic::prefix_sub(value, field));
+            }
+            2 if a.is_empty() => {
+                a.insert(monomial.signals[0], value.clone());
+                b.insert(monomial.signals[1], BigInt::from(1));
+            }
+            _ => return None,
+        }
+    }
+    Some(Constraint::new(a, b, c))
+}
+
+fn reduce(mut p: Polynomial, basis: &[Polynomial], field: &BigInt) -> Polynomial {
+    let mut remainder = Polynomial::new();
+    while let Some((monomial, value)) = p.iter().next_back().map(|(m, v)| (m.clone(), v.clone())) {
+        let divisor = basis.iter().find(|g| leading(g).is_some_and(|(lm, _)| lm.divides(&monomial)));
+        match divisor {
+            Some(g) => {
+                let (lm, lc) = leading(g).unwrap();
+                let factor = modular_arithmetic::div(&value, lc, field).unwrap();
+                let quotient = monomial.quotient(lm);
+                sub_scaled(&mut p, &factor, &quotient, g, field);
+            }
+            None => {
+                p.remove(&monomial);
+                remainder.insert(monomial, value);
+            }
+        }
+    }
+    remainder
+}
+
+fn s_polynomial(f: &Polynomial, g: &Polynomial, field: &BigInt) -> Polynomial {
+    let (lm_f, lc_f) = leading(f).unwrap();
+    let (lm_g, lc_g) = leading(g).unwrap();
+    let lcm = lm_f.lcm(lm_g);
+    let mut s = Polynomial::new();
+    let factor_f = modular_arithmetic::div(&BigInt::from(1), lc_f, field).unwrap();
+    let factor_g = modular_arithmetic::div(&BigInt::from(1), lc_g, field).unwrap();
+    sub_scaled(&mut s, &modular_arithmetic::prefix_sub(&factor_f, field), &lcm.quotient(lm_f), f, field);
+    sub_scaled(&mut s, &factor_g, &lcm.quotient(lm_g), g, field);
+    s
+}
+
+fn bounded_buchberger(generators: Vec<Polynomial>, config: &GroebnerConfig, field: &BigInt) -> Vec<Polynomial> {
+    let start = Instant::now();
+    let max_basis = 4 * generators.len() + 64;
+    let mut basis: Vec<Polynomial> = Vec::new();
+    for g in generators {
+        let mut g = reduce(g, &basis, field);
+        if !g.is_empty() {
+            make_monic(&mut g, field);
+            basis.push(g);
+        }
+    }
+    let mut pairs = LinkedList::new();
+    for j in 0..basis.len() {
+        for i in 0..j {
+            pairs.push_back((i, j));
+        }
+    }
+    let mut computed_pairs = 0;
+    while let Some((i, j)) = pairs.pop_front() {
+        let out_of_budget = match config.max_pairs {
+            Some(max_pairs) => computed_pairs >= max_pairs,
+            None => start.elapsed() > config.time_budget,
+        };
+        if out_of_budget || basis.len() >= max_basis {
+            break;
+        }
+        let lm_i = leading(&basis[i]).unwrap().0;
+        let lm_j = leading(&basis[j]).unwrap().0;
+        if lm_i.coprime(lm_j) || lm_i.lcm(lm_j).degree > config.max_degree {
+            continue;
+        }
+        computed_pairs += 1;
+        let s = s_polynomial(&basis[i], &basis[j], field);
+        let mut s = reduce(s, &basis, field);
+        if s.is_empty() {
+            continue;
+        }
+        make_monic(&mut s, field);
+        let new = basis.len();
+        basis.push(s);
+        if degree(&basis[new]) == 0 {
+            // 1 belongs to the ideal, there is nothing to deduce
+            break;
+        }
+        for i in 0..new {
+            pairs.push_back((i, new));
+        }
+    }
+    basis
+}
+
+pub fn bounded_groebner_deduction(
+    storage: &ConstraintStorage,
+    field: &BigInt,
+    config: &GroebnerConfig,
+) -> GroebnerDeduction {
+    let mut deduction = GroebnerDeduction {
+        linear: LinkedList::new(),
+        quadratic: LinkedList::new(),
+        to_delete: LinkedList::new(),
+    };
+    let mut originals = Vec::new();
+    let mut known: HashSet<HashConstraint> = HashSet::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if !constraint.is_empty() {
+            known.insert(C::get_hash_constraint(&constraint, field));
+            let prev_id = storage.read_constraint_prev_id(c_id).unwrap();
+            originals.push((from_constraint(&constraint, field), prev_id));
+        }
+    }
+    if originals.is_empty() || originals.len() > config.max_constraints {
+        return deduction;
+    }
+
+    let generators = originals.iter().map(|(p, _)| p.clone()).collect();
+    let basis = bounded_buchberger(generators, config, field);
+    if basis.iter().any(|g| degree(g) == 0) {
+        return deduction;
+    }
+
+    let mut linear = Vec::new();
+    let mut quadratic = Vec::new();
+    for g in &basis {
+        if let Some(constraint) = into_constraint(g, field) {
+            if known.contains(&C::get_hash_constraint(&constraint, field)) {
+                continue;
+            }
+            if degree(g) == 1 {
+                linear.push(g.clone());
+                deduction.linear.push_back(constraint);
+            } else {
+                quadratic.push((g.clone(), constraint));
+            }
+        }
+    }
+
+    // An original constraint is redundant when it reduces to zero using the
+    // deduced linear constraints and, at most, one of the deduced quadratic ones
+    let mut used = vec![false; quadratic.len()];
+    for (p, prev_id) in &originals {
+        if reduce(p.clone(), &linear, field).is_empty() {
+            deduction.to_delete.push_back(*prev_id);
+            continue;
+        }
+        for (pos, (q, _)) in quadratic.iter().enumerate() {
+            if q.len() >= p.len() {
+                continue;
+            }
+            let mut divisors = linear.clone();
+            divisors.push(q.clone());
+            if reduce(p.clone(), &divisors, field).is_empty() {
+                used[pos] = true;
+                deduction.to_delete.push_back(*prev_id);
+                break;
+            }
+        }
+    }
+    for (pos, (_, constraint)) in quadratic.into_iter().enumerate() {
+        if used[pos] {
+            deduction.quadratic.push_back(constraint);
+        }
+    }
+    deduction
+}
+
+// The constraints deleted by a deduction are only redundant given its linear constraints,
+// which are lost when the simplification can not turn them into substitutions (the
+// ones over forbidden signals). They are supported when every one of them reduces to
+// 0 with the substitutions of the round.
+pub fn is_supported(linear: &LinkedList<C>, substitutions: &LinkedList<S>, field: &BigInt) -> bool {
+    linear.iter().all(|constraint| {
+        let mut expression = constraint.c().clone();
+        for substitution in substitutions {
+            apply_substitution(&mut expression, substitution, field);
+        }
+        expression.values().all(|value| modular_arithmetic::add(value, &BigInt::zero(), field).is_zero())
+    })
+}
+
+// The constraint belongs to the ideal of the constraints of the storage, so it holds in
+// every solution of them. It is not proven when the basis is cut by the bounds.
+pub fn ideal_contains(storage: &ConstraintStorage, constraint: &C, field: &BigInt, config: &GroebnerConfig) -> bool {
+    let mut generators = Vec::new();
+    for c_id in storage.get_ids() {
+        let current = storage.read_constraint(c_id).unwrap();
+        if !current.is_empty() {
+            generators.push(from_constraint(&current, field));
+        }
+    }
+    if generators.is_empty() || generators.len() > config.max_constraints {
+        return false;
+    }
+    let basis = bounded_buchberger(generators, config, field);
+    if basis.iter().any(|g| degree(g) == 0) {
+        return false;
+    }
+    reduce(from_constraint(constraint, field), &basis, field).is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{field, linear, quadratic, storage};
+    use circom_algebra::algebra::ArithmeticExpression;
+
+    fn config() -> GroebnerConfig {
+        GroebnerConfig { max_constraints: 8, max_degree: 3, time_budget: Duration::from_secs(10), max_pairs: None }
+    }
+
+    #[test]
+    fn groebner_deduces_linear_and_redundant_constraints() {
+        let field = field();
+        // x*inv = 1, x*(y - z) = 0
+        let cluster = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(0, 1)]),
+            quadratic(&[(1, 1)], &[(3, 1), (4, -1)], &[]),
+        ]);
+        let deduction = bounded_groebner_deduction(&cluster, &field, &config());
+        assert_eq!(deduction.linear.len(), 1);
+        let deduced = deduction.linear.front().unwrap().c();
+        assert_eq!(deduced.len(), 2);
+        assert_eq!(modular_arithmetic::add(&deduced[&3], &deduced[&4], &field), BigInt::zero());
+        assert_eq!(deduction.to_delete.iter().cloned().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn deletions_need_the_linear_constraints() {
+        let field = field();
+        let support: LinkedList<C> = vec![linear(&[(3, 1), (4, -1)])].into_iter().collect();
+        // the constraint over forbidden signals is not substituted
+        assert!(!is_supported(&support, &LinkedList::new(), &field));
+        let to = ArithmeticExpression::Linear { coefficients: crate::test_utils::expression(&[(4, 1)]) };
+        let substitutions: LinkedList<S> = vec![S::new(3, to).unwrap()].into_iter().collect();
+        assert!(is_supported(&support, &substitutions, &field));
+    }
+
+    #[test]
+    fn ideal_contains_consequences_only() {
+        let field = field();
+        // x*inv = 1, x*y = t, x*z = t
+        let cluster = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(0, 1)]),
+            quadratic(&[(1, 1)], &[(3, 1)], &[(5, 1)]),
+            quadratic(&[(1, 1)], &[(4, 1)], &[(5, 1)]),
+        ]);
+        assert!(ideal_contains(&cluster, &linear(&[(3, 1), (4, -1)]), &field, &config()));
+        assert!(!ideal_contains(&cluster, &linear(&[(3, 1), (5, -1)]), &field, &config()));
+    }
+}
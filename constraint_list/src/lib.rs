@@ -8,10 +8,30 @@ use circom_algebra::algebra::HashConstraint;
 
 pub mod constraint_simplification;
 pub mod r1cs_porting;
+pub mod boolean_signals;
+pub mod bit_decomposition;
+pub mod signal_table;
+pub mod lint;
+pub mod graph_export;
+pub mod cluster_stats;
+pub mod system_diff;
+pub mod range_analysis;
+pub mod uniqueness;
+pub mod slicing;
 mod non_linear_simplification;
 mod preprocess_non_linear;
 mod cluster_non_linear;
 mod clusters_utils;
+mod groebner;
+mod fast_equalities;
+mod non_linear_worklist;
+mod cluster_cache;
+mod common_subexpressions;
+mod degenerate_quadratics;
+mod non_zero;
+mod witness_equalities;
+#[cfg(test)]
+mod test_utils;
 
 type C = circom_algebra::algebra::Constraint<usize>;
 type S = circom_algebra::algebra::Substitution<usize>;
@@ -0,0 +1,269 @@
+use crate::signal_table::{signal_name, signal_role, SignalRole, SignalTable};
+use circom_algebra::gaussian_elimination::full_gaussian_elimination;
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::Zero;
+use circom_algebra::simplification_utils::Config;
+use std::collections::{BTreeMap, BTreeSet, HashSet, LinkedList};
+use std::sync::Arc;
+use super::{ConstraintStorage, C};
+
+/*
+    Lints over the signal-constraint graph of a circuit. They do not prove that a
+    circuit is underconstrained, they point to the patterns that usually are the
+    cause of it so that an auditor can review them.
+*/
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LintKind {
+    // private signal that appears in no constraint
+    UnusedSignal,
+    // output whose constraints never reach an input
+    UnlinkedOutput,
+    // signal that only appears in C of a single quadratic constraint
+    OnlyInQuadraticC,
+    // output that the linear constraints fix to a constant
+    ConstantOutput,
+}
+
+impl LintKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintKind::UnusedSignal => "unused-signal",
+            LintKind::UnlinkedOutput => "unlinked-output",
+            LintKind::OnlyInQuadraticC => "only-in-quadratic-c",
+            LintKind::ConstantOutput => "constant-output",
+        }
+    }
+}
+
+pub struct LintWarning {
+    pub kind: LintKind,
+    pub signals: Vec<usize>,
+    pub constraints: Vec<usize>,
+    pub message: String,
+}
+
+// signal -> ids of the constraints in which it appears
+fn build_occurrences(storage: &ConstraintStorage) -> BTreeMap<usize, BTreeSet<usize>> {
+    let mut occurrences: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        for signal in C::take_cloned_signals(&constraint) {
+            occurrences.entry(signal).or_default().insert(c_id);
+        }
+    }
+    occurrences
+}
+
+fn unused_signals(table: &SignalTable, occurrences: &BTreeMap<usize, BTreeSet<usize>>) -> Vec<LintWarning> {
+    let mut signals: Vec<usize> = table
+        .iter()
+        .filter(|(s, info)| info.role == SignalRole::Private && !occurrences.contains_key(s))
+        .map(|(s, _)| *s)
+        .collect();
+    signals.sort_unstable();
+    signals
+        .into_iter()
+        .map(|signal| LintWarning {
+            kind: LintKind::UnusedSignal,
+            signals: vec![signal],
+            constraints: Vec::new(),
+            message: format!("private signal {} appears in no constraint", signal_name(table, signal)),
+        })
+        .collect()
+}
+
+// Outputs are connected to the rest of signals through the constraints in which they
+// appear. When the table has no inputs (the ZoKrates format does not mark them) every
+// private signal is considered a possible input.
+fn unlinked_outputs(
+    storage: &ConstraintStorage,
+    table: &SignalTable,
+    occurrences: &BTreeMap<usize, BTreeSet<usize>>,
+) -> Vec<LintWarning> {
+    let has_inputs = table.values().any(|info| info.role == SignalRole::Input);
+    let is_input = |signal: usize| match signal_role(table, signal) {
+        SignalRole::Input => true,
+        SignalRole::Private => !has_inputs,
+        _ => false,
+    };
+    let mut outputs: Vec<usize> = table
+        .iter()
+        .filter(|(_, info)| info.role == SignalRole::Output)
+        .map(|(s, _)| *s)
+        .collect();
+    outputs.sort_unstable();
+    let mut warnings = Vec::new();
+    for output in outputs {
+        let mut visited_signals = HashSet::new();
+        let mut visited_constraints = BTreeSet::new();
+        let mut pending = vec![output];
+        let mut linked = false;
+        visited_signals.insert(output);
+        while let Some(signal) = pending.pop() {
+            if is_input(signal) {
+                linked = true;
+                break;
+            }
+            for c_id in occurrences.get(&signal).into_iter().flatten() {
+                if !visited_constraints.insert(*c_id) {
+                    continue;
+                }
+                let constraint = storage.read_constraint(*c_id).unwrap();
+                for other in C::take_cloned_signals(&constraint) {
+                    if visited_signals.insert(other) {
+                        pending.push(other);
+                    }
+                }
+            }
+        }
+        if !linked {
+            warnings.push(LintWarning {
+                kind: LintKind::UnlinkedOutput,
+                signals: vec![output],
+                constraints: visited_constraints.into_iter().collect(),
+                message: format!("output {} is not linked to any input", signal_name(table, output)),
+            });
+        }
+    }
+    warnings
+}
+
+fn only_in_quadratic_c(
+    storage: &ConstraintStorage,
+    table: &SignalTable,
+    occurrences: &BTreeMap<usize, BTreeSet<usize>>,
+) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    for (signal, c_ids) in occurrences {
+        if c_ids.len() != 1 || signal_role(table, *signal) == SignalRole::Output {
+            continue;
+        }
+        let c_id = *c_ids.iter().next().unwrap();
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if C::is_linear(&constraint)
+            || constraint.a().contains_key(signal)
+            || constraint.b().contains_key(signal)
+        {
+            continue;
+        }
+        warnings.push(LintWarning {
+            kind: LintKind::OnlyInQuadraticC,
+            signals: vec![*signal],
+            constraints: vec![c_id],
+            message: format!(
+                "signal {} only appears in C of the quadratic constraint {}",
+                signal_name(table, *signal),
+                c_id
+            ),
+        });
+    }
+    warnings
+}
+
+// The linear constraints are reduced without using the outputs as pivots, so the
+// rows that are left with a single output state its value.
+fn constant_outputs(storage: &ConstraintStorage, table: &SignalTable, field: &BigInt) -> Vec<LintWarning> {
+    let constant = C::constant_coefficient();
+    let mut linear = LinkedList::new();
+    let mut linear_ids = Vec::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if C::is_linear(&constraint) && !constraint.is_empty() {
+            linear_ids.push((c_id, C::take_cloned_signals(&constraint)));
+            linear.push_back(constraint);
+        }
+    }
+    let outputs: HashSet<usize> = table
+        .iter()
+        .filter(|(_, info)| info.role == SignalRole::Output)
+        .map(|(s, _)| *s)
+        .collect();
+    let config = Config { field: field.clone(), constraints: linear, forbidden: Arc::new(outputs.clone()) };
+    let (simplified, _) = full_gaussian_elimination(config);
+    let mut values = BTreeMap::new();
+    for constraint in simplified.constraints {
+        let terms: Vec<(&usize, &BigInt)> = constraint.c().iter().filter(|(_, v)| !v.is_zero()).collect();
+        let signals: Vec<usize> = terms.iter().map(|(s, _)| **s).filter(|s| *s != constant).collect();
+        if signals.len() != 1 || !outputs.contains(&signals[0]) {
+            continue;
+        }
+        let coefficient = constraint.c().get(&signals[0]).unwrap();
+        let independent = constraint.c().get(&constant).cloned().unwrap_or_else(BigInt::zero);
+        let value = modular_arithmetic::div(&independent, coefficient, field).unwrap();
+        values.insert(signals[0], modular_arithmetic::prefix_sub(&value, field));
+    }
+    values
+        .into_iter()
+        .map(|(output, value)| LintWarning {
+            kind: LintKind::ConstantOutput,
+            signals: vec![output],
+            constraints: linear_ids.iter().filter(|(_, s)| s.contains(&output)).map(|(c_id, _)| *c_id).collect(),
+            message: format!("output {} is always equal to {}", signal_name(table, output), value),
+        })
+        .collect()
+}
+
+pub fn lint(storage: &ConstraintStorage, table: &SignalTable, field: &BigInt) -> Vec<LintWarning> {
+    let occurrences = build_occurrences(storage);
+    let mut warnings = unused_signals(table, &occurrences);
+    warnings.append(&mut unlinked_outputs(storage, table, &occurrences));
+    warnings.append(&mut only_in_quadratic_c(storage, table, &occurrences));
+    warnings.append(&mut constant_outputs(storage, table, field));
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal_table::SignalInfo;
+    use crate::test_utils::{field, linear, quadratic, storage};
+
+    fn table(roles: &[SignalRole]) -> SignalTable {
+        roles
+            .iter()
+            .enumerate()
+            .map(|(signal, role)| (signal, SignalInfo { name: format!("s{}", signal), role: *role }))
+            .collect()
+    }
+
+    #[test]
+    fn lints_of_an_underconstrained_circuit() {
+        let field = field();
+        use SignalRole::*;
+        // ~one, out, in, x, unused, out2, k, v
+        let table = table(&[Constant, Output, Input, Private, Private, Output, Output, Private]);
+        // in*in = x, out = in + 1, out2*out2 = v, k = 5
+        let circuit = storage(vec![
+            quadratic(&[(2, 1)], &[(2, 1)], &[(3, 1)]),
+            linear(&[(1, 1), (2, -1), (0, -1)]),
+            quadratic(&[(5, 1)], &[(5, 1)], &[(7, 1)]),
+            linear(&[(6, 1), (0, -5)]),
+        ]);
+        let warnings: Vec<(LintKind, Vec<usize>)> =
+            lint(&circuit, &table, &field).into_iter().map(|w| (w.kind, w.signals)).collect();
+        assert_eq!(warnings, vec![
+            (LintKind::UnusedSignal, vec![4]),
+            (LintKind::UnlinkedOutput, vec![5]),
+            (LintKind::UnlinkedOutput, vec![6]),
+            (LintKind::OnlyInQuadraticC, vec![3]),
+            (LintKind::OnlyInQuadraticC, vec![7]),
+            (LintKind::ConstantOutput, vec![6]),
+        ]);
+    }
+
+    #[test]
+    fn constant_output_value() {
+        let field = field();
+        use SignalRole::*;
+        let table = table(&[Constant, Output, Private]);
+        // out = 2*x, x = 3
+        let circuit = storage(vec![linear(&[(1, 1), (2, -2)]), linear(&[(2, 1), (0, -3)])]);
+        let warnings = lint(&circuit, &table, &field);
+        let constant: Vec<&LintWarning> = warnings.iter().filter(|w| w.kind == LintKind::ConstantOutput).collect();
+        assert_eq!(constant.len(), 1);
+        assert_eq!(constant[0].message, "output s1 is always equal to 6");
+        assert_eq!(constant[0].constraints, vec![0]);
+    }
+}
@@ -2,21 +2,36 @@ use circom_algebra::algebra::{Constraint};
 use std::collections::{HashSet, LinkedList};
 use super::{ConstraintStorage};
 use super::preprocess_non_linear::*;
+use crate::groebner::{bounded_groebner_deduction, GroebnerConfig, GroebnerDeduction};
 use circom_algebra::num_bigint::BigInt;
+use crate::cluster_cache::{canonicalize, CachedDeduction, ClusterCache};
 use std::sync::Arc;
 
 
 pub struct NonLinearClustersConfig {
     pub field: BigInt,
     pub storage: ConstraintStorage,
+    // the clusters are built in the order of the constraints of the storage
+    pub deterministic: bool,
 }
 
 
 pub fn obtain_non_linear_clusters(config: NonLinearClustersConfig) -> LinkedList<ConstraintStorage>{
+    obtain_non_linear_clusters_counting(config).0
+}
+
+// Also returns the number of constraints discarded by compute_zero_constraints
+pub fn obtain_non_linear_clusters_counting(config: NonLinearClustersConfig) -> (LinkedList<ConstraintStorage>, usize) {
     let mut processed_constraints = ProcessedConstraints::new(&config.storage, &config.field);
-    processed_constraints.compute_zero_constraints(&config.storage, &config.field);
-    processed_constraints.compute_clusters_constraints(&config.storage);
-    processed_constraints.clusters
+    let removed = processed_constraints.compute_zero_constraints(&config.storage, &config.field);
+    processed_constraints.compute_clusters_constraints(&config.storage, config.deterministic);
+    (processed_constraints.clusters, removed)
+}
+
+
+// Clusters with more constraints than groebner.max_constraints are not analyzed
+pub fn deduce_groebner_constraints(config: &NonLinearClustersConfig, groebner: &GroebnerConfig) -> GroebnerDeduction {
+    bounded_groebner_deduction(&config.storage, &config.field, groebner)
 }
 
 
@@ -24,20 +39,36 @@ pub struct NonLinearConfig {
     pub field: BigInt,
     pub storage: ConstraintStorage,
     pub forbidden: Arc<HashSet<usize>>,
+    // the monomials and the deduced constraints are taken in order
+    pub deterministic: bool,
+    pub cache: Option<Arc<ClusterCache>>,
 }
 
 pub fn deduce_linear_constraints(config: NonLinearConfig)
  -> (LinkedList<Constraint<usize>>, LinkedList<usize>)
 {
-
-    let config = crate::non_linear_simplification::NonLinearConfig {
-        field: config.field,
-        storage: config.storage,
-        forbidden: Arc::clone(&config.forbidden),
-    };
-
-    crate::cluster_non_linear::obtain_linear_constraints(config)
+    match &config.cache {
+        Some(cache) => {
+            let (canonical, storage) = canonicalize(&config.storage);
+            let deduction = match cache.get(&canonical) {
+                Some(deduction) => deduction,
+                None => {
+                    let canonical_config = NonLinearConfig {
+                        field: config.field.clone(),
+                        storage,
+                        forbidden: Arc::clone(&config.forbidden),
+                        deterministic: config.deterministic,
+                        cache: None,
+                    };
+                    let (linear, deletable) = crate::cluster_non_linear::obtain_linear_constraints(canonical_config);
+                    let deduction = CachedDeduction { linear, deletable };
+                    cache.insert(&canonical, deduction.clone());
+                    deduction
+                }
+            };
+            let deduction = canonical.translate(&deduction);
+            (deduction.linear, deduction.deletable)
+        }
+        None => crate::cluster_non_linear::obtain_linear_constraints(config),
+    }
 }
-
-
-
@@ -0,0 +1,219 @@
+use std::collections::{BTreeSet, HashMap, HashSet, LinkedList};
+use super::{ConstraintStorage, C};
+
+/*
+    Worklist of the non-linear rounds. The monomials of every constraint are indexed,
+    and after a round only the clusters that contain a constraint changed by the
+    substitutions or deletions (or that shared a monomial with it before the change)
+    are rebuilt, instead of re-clustering the whole storage. A cluster with no changes
+    deduces again what it deduced in the previous round, so skipping it does not
+    change the result. build_clusters_nonlinear joins the constraints through the
+    first signal of their monomials, so that signal is what gets indexed.
+*/
+
+pub struct NonLinearWorklist {
+    // first signals of the monomials of each constraint the last time it was indexed
+    constraint_monomials: HashMap<usize, Vec<usize>>,
+    monomial_constraints: HashMap<usize, HashSet<usize>>,
+    affected: BTreeSet<usize>,
+}
+
+fn monomials_of(storage: &ConstraintStorage, c_id: usize) -> Vec<usize> {
+    let constraint = storage.read_constraint(c_id).unwrap();
+    if C::is_empty(&constraint) {
+        return Vec::new();
+    }
+    let first_signals: BTreeSet<usize> =
+        constraint.take_possible_cloned_monomials().into_iter().map(|(first, _)| first).collect();
+    first_signals.into_iter().collect()
+}
+
+impl NonLinearWorklist {
+    pub fn new(storage: &ConstraintStorage) -> NonLinearWorklist {
+        let mut worklist = NonLinearWorklist {
+            constraint_monomials: HashMap::new(),
+            monomial_constraints: HashMap::new(),
+            affected: BTreeSet::new(),
+        };
+        for c_id in storage.get_ids() {
+            let monomials = monomials_of(storage, c_id);
+            worklist.index(c_id, monomials);
+        }
+        worklist
+    }
+
+    fn index(&mut self, c_id: usize, monomials: Vec<usize>) {
+        for monomial in &monomials {
+            self.monomial_constraints.entry(*monomial).or_default().insert(c_id);
+        }
+        if !monomials.is_empty() {
+            self.constraint_monomials.insert(c_id, monomials);
+        }
+    }
+
+    fn unindex(&mut self, c_id: usize) -> Vec<usize> {
+        let monomials = self.constraint_monomials.remove(&c_id).unwrap_or_default();
+        for monomial in &monomials {
+            if let Some(constraints) = self.monomial_constraints.get_mut(monomial) {
+                constraints.remove(&c_id);
+                if constraints.is_empty() {
+                    self.monomial_constraints.remove(monomial);
+                }
+            }
+        }
+        monomials
+    }
+
+    // the constraint was replaced, added or deleted
+    pub fn touch(&mut self, c_id: usize) {
+        self.affected.insert(c_id);
+    }
+
+    pub fn no_affected(&self) -> usize {
+        self.affected.len()
+    }
+
+    // Clusters (of more than one constraint) that contain an affected constraint. They
+    // are given in the same order as build_clusters_nonlinear would give them.
+    pub fn affected_clusters(&mut self, storage: &ConstraintStorage) -> LinkedList<ConstraintStorage> {
+        let mut pending = Vec::new();
+        for c_id in std::mem::take(&mut self.affected) {
+            let old_monomials = self.unindex(c_id);
+            // the constraints that shared a monomial with the old version may now be
+            // in a different cluster
+            for monomial in &old_monomials {
+                if let Some(constraints) = self.monomial_constraints.get(monomial) {
+                    pending.extend(constraints.iter().cloned());
+                }
+            }
+            let monomials = monomials_of(storage, c_id);
+            self.index(c_id, monomials);
+            pending.push(c_id);
+        }
+
+        let mut visited = HashSet::new();
+        let mut clusters = Vec::new();
+        for start in pending {
+            if !self.constraint_monomials.contains_key(&start) || !visited.insert(start) {
+                continue;
+            }
+            let mut cluster = vec![start];
+            let mut next = 0;
+            while next < cluster.len() {
+                let c_id = cluster[next];
+                next += 1;
+                for monomial in &self.constraint_monomials[&c_id] {
+                    for other in &self.monomial_constraints[monomial] {
+                        if visited.insert(*other) {
+                            cluster.push(*other);
+                        }
+                    }
+                }
+            }
+            if cluster.len() > 1 {
+                cluster.sort_unstable();
+                clusters.push(cluster);
+            }
+        }
+        // build_clusters_nonlinear leaves each cluster in the slot of its last constraint
+        clusters.sort_unstable_by_key(|cluster| *cluster.last().unwrap());
+
+        let mut storages = LinkedList::new();
+        for cluster in clusters {
+            let mut new_storage = ConstraintStorage::new();
+            for c_id in cluster {
+                let constraint = storage.read_constraint(c_id).unwrap();
+                let prev_constraint_id = storage.read_constraint_prev_id(c_id).unwrap();
+                new_storage.add_constraint_with_prev_id(constraint, prev_constraint_id);
+            }
+            storages.push_back(new_storage);
+        }
+        storages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraint_simplification::build_clusters_nonlinear;
+    use crate::test_utils::{quadratic, storage};
+
+    fn ids(clusters: &LinkedList<ConstraintStorage>) -> Vec<Vec<usize>> {
+        clusters
+            .iter()
+            .map(|cluster| cluster.get_ids().into_iter().map(|id| cluster.read_constraint_prev_id(id).unwrap()).collect())
+            .collect()
+    }
+
+    // the clusters of the full re-clustering that contain one of the constraints
+    fn full_reclustering(storage: &ConstraintStorage, constraints: &[usize]) -> Vec<Vec<usize>> {
+        ids(&build_clusters_nonlinear(storage))
+            .into_iter()
+            .filter(|cluster| cluster.iter().any(|c_id| constraints.contains(c_id)))
+            .collect()
+    }
+
+    #[test]
+    fn affected_clusters_match_full_reclustering() {
+        // x*y = a, x*y = b, z*w = c, (z + u)*w = d, w*v = f, p*q = g
+        let mut constraints = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(10, 1)]),
+            quadratic(&[(1, 1)], &[(2, 1)], &[(11, 1)]),
+            quadratic(&[(3, 1)], &[(4, 1)], &[(12, 1)]),
+            quadratic(&[(3, 1), (5, 1)], &[(4, 1)], &[(13, 1)]),
+            quadratic(&[(4, 1)], &[(8, 1)], &[(14, 1)]),
+            quadratic(&[(6, 1)], &[(7, 1)], &[(15, 1)]),
+        ]);
+        let mut worklist = NonLinearWorklist::new(&constraints);
+
+        // x*y = b becomes p*q = b: it leaves the cluster of x*y = a and joins p*q = g
+        constraints.replace(1, quadratic(&[(6, 1)], &[(7, 1)], &[(11, 1)]));
+        worklist.touch(1);
+        assert_eq!(worklist.no_affected(), 1);
+        let affected = ids(&worklist.affected_clusters(&constraints));
+        assert_eq!(affected, vec![vec![1, 5]]);
+        assert_eq!(affected, full_reclustering(&constraints, &[0, 1]));
+
+        // (z + u)*w = d becomes z*w = d, w*v = f is left alone
+        constraints.replace(3, quadratic(&[(3, 1)], &[(4, 1)], &[(13, 1)]));
+        worklist.touch(3);
+        let affected = ids(&worklist.affected_clusters(&constraints));
+        assert_eq!(affected, vec![vec![2, 3]]);
+        assert_eq!(affected, full_reclustering(&constraints, &[2, 3, 4]));
+        assert_eq!(worklist.no_affected(), 0);
+    }
+
+    #[test]
+    fn monomials_join_through_their_first_signal() {
+        // x*y = a, z*w = b, x*z = c: x*z = c joins x*y = a (z*w = b is alone)
+        let mut constraints = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(10, 1)]),
+            quadratic(&[(3, 1)], &[(4, 1)], &[(11, 1)]),
+            quadratic(&[(5, 1)], &[(6, 1)], &[(12, 1)]),
+        ]);
+        let mut worklist = NonLinearWorklist::new(&constraints);
+        constraints.replace(2, quadratic(&[(1, 1)], &[(3, 1)], &[(12, 1)]));
+        worklist.touch(2);
+        let affected = ids(&worklist.affected_clusters(&constraints));
+        assert_eq!(affected, vec![vec![0, 2]]);
+        assert_eq!(affected, full_reclustering(&constraints, &[2]));
+    }
+
+    #[test]
+    fn deleted_and_added_constraints() {
+        // x*y = a, x*y = b, x*y = c
+        let mut constraints = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(10, 1)]),
+            quadratic(&[(1, 1)], &[(2, 1)], &[(11, 1)]),
+            quadratic(&[(1, 1)], &[(2, 1)], &[(12, 1)]),
+        ]);
+        let mut worklist = NonLinearWorklist::new(&constraints);
+        constraints.replace(1, C::empty());
+        worklist.touch(1);
+        let added = constraints.add_constraint(quadratic(&[(2, 1)], &[(1, 1)], &[(13, 1)]));
+        worklist.touch(added);
+        let affected = ids(&worklist.affected_clusters(&constraints));
+        assert_eq!(affected, vec![vec![0, 2, 3]]);
+        assert_eq!(affected, full_reclustering(&constraints, &[0, 1, 2, 3]));
+    }
+}
@@ -0,0 +1,240 @@
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::{One, Zero};
+use std::collections::{BTreeMap, HashMap, HashSet, LinkedList};
+use super::{ConstraintStorage, C};
+use crate::range_analysis::{excludes_zero, SignalRanges};
+
+/*
+    Inference of non-zero linear expressions and cancellation of the non-zero factors of
+    the quadratic constraints. A*B = C proves A and B non-zero when C is non-zero (the
+    inverse gadget x * inv = 1 is the usual case), and C non-zero when A and B are, so
+    the set is computed as a fixpoint, starting from the expressions whose range does
+    not contain 0. Then, with F non-zero:
+        F*B = k*F      gives  B = k  (and F*B = 0 gives B = 0)
+        F*B1 = C, F*B2 = C  give  B1 = B2
+    where the constraints are compared up to a factor. Also G*B = k*G gives G = 0 when
+    B - k is non-zero.
+    The monomials of take_cloned_monomials do not keep the factors A and B of a
+    product, which are the ones cancelled here, so the sides are kept as linear
+    expressions normalized up to a factor. The linear constraints found go through the
+    usual substitution pipeline.
+*/
+
+// Expression with a non zero coefficient in its smallest signal, which is 1
+pub type Expression = Vec<(usize, BigInt)>;
+
+fn normalize(expression: &HashMap<usize, BigInt>, field: &BigInt) -> Option<(Expression, BigInt)> {
+    let zero = BigInt::zero();
+    let mut terms: Expression = expression
+        .iter()
+        .map(|(signal, value)| (*signal, modular_arithmetic::add(value, &zero, field)))
+        .filter(|(_, value)| !value.is_zero())
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    terms.sort();
+    let factor = terms[0].1.clone();
+    let inverse = modular_arithmetic::div(&BigInt::one(), &factor, field).ok()?;
+    for (_, value) in terms.iter_mut() {
+        *value = modular_arithmetic::mul(value, &inverse, field);
+    }
+    Some((terms, factor))
+}
+
+fn scale(expression: &HashMap<usize, BigInt>, factor: &BigInt, field: &BigInt) -> HashMap<usize, BigInt> {
+    expression.iter().map(|(signal, value)| (*signal, modular_arithmetic::mul(value, factor, field))).collect()
+}
+
+// left - right = 0, None when it is 0 = 0
+fn difference(left: &HashMap<usize, BigInt>, right: &HashMap<usize, BigInt>, field: &BigInt) -> Option<C> {
+    let mut expression = left.clone();
+    for (signal, value) in right {
+        let current = expression.remove(signal).unwrap_or_else(BigInt::zero);
+        let new_value = modular_arithmetic::sub(&current, value, field);
+        if !new_value.is_zero() {
+            expression.insert(*signal, new_value);
+        }
+    }
+    expression.retain(|_, value| !value.is_zero());
+    if expression.is_empty() {
+        None
+    } else {
+        Some(C::new(HashMap::new(), HashMap::new(), expression))
+    }
+}
+
+// The expression is in the set of non-zero expressions or its range does not contain 0
+pub fn proven_non_zero(expression: &HashMap<usize, BigInt>, non_zero: &HashSet<Expression>, ranges: &SignalRanges, field: &BigInt) -> bool {
+    excludes_zero(expression, ranges, field)
+        || normalize(expression, field).is_some_and(|(expression, _)| non_zero.contains(&expression))
+}
+
+pub fn infer_non_zero(storage: &ConstraintStorage, ranges: &SignalRanges, field: &BigInt) -> HashSet<Expression> {
+    let mut non_zero = HashSet::new();
+    // the non zero constants
+    non_zero.insert(vec![(C::constant_coefficient(), BigInt::one())]);
+    let mut sides = Vec::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if C::is_empty(&constraint) || C::is_linear(&constraint) {
+            continue;
+        }
+        for side in [constraint.a(), constraint.b(), constraint.c()] {
+            if excludes_zero(side, ranges, field) {
+                non_zero.extend(normalize(side, field).map(|(side, _)| side));
+            }
+        }
+        let a = normalize(constraint.a(), field).map(|(a, _)| a);
+        let b = normalize(constraint.b(), field).map(|(b, _)| b);
+        let c = normalize(constraint.c(), field).map(|(c, _)| c);
+        if let (Some(a), Some(b)) = (a, b) {
+            sides.push((a, b, c));
+        }
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (a, b, c) in &sides {
+            if c.as_ref().is_some_and(|c| non_zero.contains(c)) {
+                changed |= non_zero.insert(a.clone());
+                changed |= non_zero.insert(b.clone());
+            }
+            if let Some(c) = c {
+                if non_zero.contains(a) && non_zero.contains(b) {
+                    changed |= non_zero.insert(c.clone());
+                }
+            }
+        }
+    }
+    non_zero
+}
+
+// The linear constraints obtained by cancelling the non-zero factors, and the number
+// of non-zero expressions found
+pub fn cancel_non_zero_factors(storage: &ConstraintStorage, ranges: &SignalRanges, field: &BigInt) -> (LinkedList<C>, usize) {
+    let non_zero = infer_non_zero(storage, ranges, field);
+    let mut linear = LinkedList::new();
+    // (F, C normalized) -> V for the constraints F*V = C
+    let mut products: BTreeMap<(Expression, Expression), HashMap<usize, BigInt>> = BTreeMap::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if C::is_empty(&constraint) || C::is_linear(&constraint) {
+            continue;
+        }
+        for (factor_side, other_side) in [(constraint.a(), constraint.b()), (constraint.b(), constraint.a())] {
+            let (factor, coefficient) = match normalize(factor_side, field) {
+                Some(normalized) => normalized,
+                None => continue,
+            };
+            let is_non_zero = non_zero.contains(&factor);
+            // coefficient*F*B = C
+            let other = scale(other_side, &coefficient, field);
+            let product = normalize(constraint.c(), field);
+            if let Some((c, k)) = &product {
+                if *c == factor {
+                    // F*(B' - k) = 0, the normalization moves the linear terms of A*B to C
+                    let mut constant = HashMap::new();
+                    constant.insert(C::constant_coefficient(), k.clone());
+                    let residual = difference(&other, &constant, field);
+                    if is_non_zero {
+                        linear.extend(residual);
+                        break;
+                    }
+                    let residual_non_zero = residual
+                        .as_ref()
+                        .is_some_and(|residual| proven_non_zero(residual.c(), &non_zero, ranges, field));
+                    if residual_non_zero {
+                        linear.extend(difference(factor_side, &HashMap::new(), field));
+                        break;
+                    }
+                }
+            }
+            if !is_non_zero {
+                continue;
+            }
+            let (key, value) = match product {
+                None => {
+                    linear.extend(difference(&other, &HashMap::new(), field));
+                    break;
+                }
+                Some((c, k)) => {
+                    let inverse = modular_arithmetic::div(&BigInt::one(), &k, field).unwrap();
+                    ((factor, c), scale(&other, &inverse, field))
+                }
+            };
+            match products.get(&key) {
+                Some(first) => linear.extend(difference(first, &value, field)),
+                None => {
+                    products.insert(key, value);
+                }
+            }
+        }
+    }
+    (linear, non_zero.len() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{expression, field, quadratic, storage};
+
+    // x*inv = 1 proves x non-zero
+    fn inverse() -> C {
+        quadratic(&[(1, 1)], &[(2, 1)], &[(0, 1)])
+    }
+
+    #[test]
+    fn factors_of_a_non_zero_product_are_non_zero() {
+        let field = field();
+        // x*inv = 1, x*y = z
+        let constraints = storage(vec![inverse(), quadratic(&[(1, 1)], &[(3, 1)], &[(4, 1)])]);
+        let non_zero = infer_non_zero(&constraints, &SignalRanges::new(), &field);
+        assert!(non_zero.contains(&vec![(1, BigInt::one())]));
+        assert!(non_zero.contains(&vec![(2, BigInt::one())]));
+        assert!(!non_zero.contains(&vec![(3, BigInt::one())]));
+        assert!(!non_zero.contains(&vec![(4, BigInt::one())]));
+    }
+
+    #[test]
+    fn non_zero_factor_is_cancelled() {
+        let field = field();
+        // x*inv = 1, 2x*y = 6x gives y = 3
+        let constraints = storage(vec![inverse(), quadratic(&[(1, 2)], &[(3, 1)], &[(1, 6)])]);
+        let (linear, found) = cancel_non_zero_factors(&constraints, &SignalRanges::new(), &field);
+        // x, inv and y, as 6x is non-zero
+        assert_eq!(found, 3);
+        assert_eq!(linear.len(), 1);
+        assert_eq!(linear.front().unwrap().c(), &expression(&[(3, 2), (0, -6)]));
+    }
+
+    #[test]
+    fn same_product_with_a_non_zero_factor() {
+        let field = field();
+        // x*inv = 1, x*y1 = z, x*y2 = z gives y1 = y2, z*w = z says nothing
+        let constraints = storage(vec![
+            inverse(),
+            quadratic(&[(1, 1)], &[(3, 1)], &[(5, 1)]),
+            quadratic(&[(1, 1)], &[(4, 1)], &[(5, 1)]),
+            quadratic(&[(5, 1)], &[(6, 1)], &[(5, 1)]),
+        ]);
+        let (linear, _) = cancel_non_zero_factors(&constraints, &SignalRanges::new(), &field);
+        assert_eq!(linear.len(), 1);
+        assert_eq!(linear.front().unwrap().c(), &expression(&[(3, 1), (4, -1)]));
+    }
+
+    #[test]
+    fn factor_is_zero_when_the_residual_is_non_zero() {
+        let field = field();
+        // x*inv = 1, g*(x + 3) = 3g gives g*x = 0, so g = 0
+        let product = quadratic(&[(5, 1)], &[(1, 1), (0, 3)], &[(5, 3)]);
+        let constraints = storage(vec![inverse(), product.clone()]);
+        let (linear, _) = cancel_non_zero_factors(&constraints, &SignalRanges::new(), &field);
+        assert_eq!(linear.len(), 1);
+        assert_eq!(linear.front().unwrap().c(), &expression(&[(5, 1)]));
+        // x may be 0 without the inverse
+        let (linear, _) = cancel_non_zero_factors(&storage(vec![product]), &SignalRanges::new(), &field);
+        assert!(linear.is_empty());
+    }
+}
@@ -63,8 +63,9 @@ impl ProcessedConstraints{
         }
     }
 
-    pub fn compute_zero_constraints(&mut self, storage: &ConstraintStorage, field: &BigInt){
-
+    // Returns the number of constraints discarded
+    pub fn compute_zero_constraints(&mut self, storage: &ConstraintStorage, field: &BigInt) -> usize {
+        let no_constraints = self.map_constraints_monomials.len();
         for monomial in &self.list_monomials{
             compute_zero_constraints_monomial(
                 &mut self.map_constraints_monomials, 
@@ -74,17 +75,22 @@ impl ProcessedConstraints{
                 field,
             );
         }
+        no_constraints - self.map_constraints_monomials.len()
     }
 
 
-    pub fn compute_clusters_constraints(&mut self, storage: &ConstraintStorage) {
+    pub fn compute_clusters_constraints(&mut self, storage: &ConstraintStorage, deterministic: bool) {
 
         let no_constraints = self.map_constraints_monomials.len();
         let mut arena = ClusterArena::with_capacity(no_constraints);
         let mut cluster_to_current = ClusterPath::with_capacity(no_constraints);
         let mut monomial_to_cluster = HashMap::new();
+        let mut constraints_monomials: Vec<(&ConstraintID, &Vec<Monomial>)> = self.map_constraints_monomials.iter().collect();
+        if deterministic {
+            constraints_monomials.sort_unstable_by_key(|(c_id, _)| **c_id);
+        }
     
-        for (c_id, monomials) in &self.map_constraints_monomials {
+        for (c_id, monomials) in constraints_monomials {
             let dest = ClusterArena::len(&arena);
             ClusterArena::push(&mut arena, Some(Cluster::new(c_id)));
             Vec::push(&mut cluster_to_current, dest);
@@ -108,7 +114,11 @@ impl ProcessedConstraints{
             if let Some(cluster) = cluster {
                 if Cluster::size(&cluster) > 1 {
                     let mut new_storage = ConstraintStorage::new();
-                    for constraint_id in cluster.constraints{
+                    let mut constraint_ids: Vec<&ConstraintID> = cluster.constraints.into_iter().collect();
+                    if deterministic {
+                        constraint_ids.sort_unstable();
+                    }
+                    for constraint_id in constraint_ids{
                         let constraint = storage.read_constraint(*constraint_id).unwrap();
                         let prev_constraint_id = storage.read_constraint_prev_id(*constraint_id).unwrap();
                         new_storage.add_constraint_with_prev_id(constraint, prev_constraint_id);
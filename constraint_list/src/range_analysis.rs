@@ -0,0 +1,245 @@
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::{One, Signed, Zero};
+use std::collections::{BTreeMap, HashMap, LinkedList};
+use super::{ConstraintStorage, C};
+use crate::degenerate_quadratics::allowed_values;
+
+/*
+    Range analysis of the signals. A signal whose value (as an integer in [0, p)) is
+    bounded gets a range: the roots of its quadratic constraints (b*(b - 1) = 0 gives
+    [0, 1]) and the linear constraints that define a signal as a sum of bounded terms,
+    when the sum can not wrap around p. With the ranges, a linear constraint whose
+    terms are all bounded and whose sum can only be a multiple of p at one end of its
+    interval (a sum of non-negative terms equal to 0) forces every term to its bound,
+    so it is split in one constraint per signal.
+*/
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Range {
+    pub min: BigInt,
+    pub max: BigInt,
+}
+
+pub type SignalRanges = BTreeMap<usize, Range>;
+
+// The coefficient in (-p/2, p/2]
+fn signed(value: &BigInt, field: &BigInt) -> BigInt {
+    let value = ((value % field) + field) % field;
+    if &value * 2 > *field {
+        value - field
+    } else {
+        value
+    }
+}
+
+fn floor_div(value: &BigInt, field: &BigInt) -> BigInt {
+    let quotient = value / field;
+    if value.is_negative() && !(value % field).is_zero() {
+        quotient - 1
+    } else {
+        quotient
+    }
+}
+
+// Interval of the integer sum of the terms of the expression but skip, None when a
+// signal is not bounded
+fn interval(expression: &HashMap<usize, BigInt>, skip: Option<usize>, ranges: &SignalRanges, field: &BigInt) -> Option<(BigInt, BigInt)> {
+    let constant = C::constant_coefficient();
+    let (mut low, mut high) = (BigInt::zero(), BigInt::zero());
+    for (signal, value) in expression {
+        if Some(*signal) == skip {
+            continue;
+        }
+        let coefficient = signed(value, field);
+        if *signal == constant {
+            low += &coefficient;
+            high += &coefficient;
+            continue;
+        }
+        let range = ranges.get(signal)?;
+        if coefficient.is_negative() {
+            low += &coefficient * &range.max;
+            high += &coefficient * &range.min;
+        } else {
+            low += &coefficient * &range.min;
+            high += &coefficient * &range.max;
+        }
+    }
+    Some((low, high))
+}
+
+// The range of signal in expression = 0, when its coefficient is 1 or -1, the rest of
+// signals are bounded and their sum does not wrap around p
+fn defined_range(expression: &HashMap<usize, BigInt>, signal: usize, ranges: &SignalRanges, field: &BigInt) -> Option<Range> {
+    let coefficient = signed(&expression[&signal], field);
+    let (low, high) = interval(expression, Some(signal), ranges, field)?;
+    // signal = -rest / coefficient
+    let (low, high) = if coefficient == BigInt::one() {
+        (-high, -low)
+    } else if coefficient == -BigInt::one() {
+        (low, high)
+    } else {
+        return None;
+    };
+    if &high - &low >= *field {
+        return None;
+    }
+    let shift = floor_div(&low, field) * field;
+    let range = Range { min: low - &shift, max: high - &shift };
+    if range.max < *field {
+        Some(range)
+    } else {
+        None
+    }
+}
+
+// The expression can not be 0: its sum does not wrap around p and its interval has
+// no multiple of p
+pub fn excludes_zero(expression: &HashMap<usize, BigInt>, ranges: &SignalRanges, field: &BigInt) -> bool {
+    match interval(expression, None, ranges, field) {
+        Some((low, high)) => &high - &low < *field && floor_div(&high, field) * field < low,
+        None => false,
+    }
+}
+
+pub fn infer_ranges(linear: &LinkedList<C>, storage: &ConstraintStorage, field: &BigInt) -> SignalRanges {
+    let constant = C::constant_coefficient();
+    let mut ranges = SignalRanges::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if C::is_empty(&constraint) || C::is_linear(&constraint) {
+            continue;
+        }
+        if let Some((form, values)) = allowed_values(&constraint, field) {
+            if form.len() != 1 || form[0].0 == constant || values.is_empty() {
+                continue;
+            }
+            let range = Range { min: values[0].clone(), max: values[values.len() - 1].clone() };
+            let current = ranges.entry(form[0].0).or_insert_with(|| range.clone());
+            if &current.max - &current.min > &range.max - &range.min {
+                *current = range;
+            }
+        }
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for constraint in linear {
+            let mut unbounded = constraint.c().keys().filter(|signal| **signal != constant && !ranges.contains_key(signal));
+            let signal = match (unbounded.next(), unbounded.next()) {
+                (Some(signal), None) => *signal,
+                _ => continue,
+            };
+            if let Some(range) = defined_range(constraint.c(), signal, &ranges, field) {
+                ranges.insert(signal, range);
+                changed = true;
+            }
+        }
+    }
+    ranges
+}
+
+// Replaces the linear constraints that force all their terms to a bound by the
+// constraints signal = bound. Also returns the number of split constraints.
+pub fn split_bounded_sums(linear: LinkedList<C>, ranges: &SignalRanges, field: &BigInt) -> (LinkedList<C>, usize) {
+    let constant = C::constant_coefficient();
+    let mut result = LinkedList::new();
+    let mut split = 0;
+    for constraint in linear {
+        let at_bound = match interval(constraint.c(), None, ranges, field) {
+            Some((low, high)) if low != high && &high - &low < *field => {
+                // the only multiple of p the sum can be
+                let multiple = floor_div(&high, field) * field;
+                if multiple == low {
+                    Some(false)
+                } else if multiple == high {
+                    Some(true)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let at_max = match at_bound {
+            Some(at_max) => at_max,
+            None => {
+                result.push_back(constraint);
+                continue;
+            }
+        };
+        let mut signals: Vec<&usize> = constraint.c().keys().filter(|signal| **signal != constant).collect();
+        signals.sort();
+        for signal in signals {
+            let range = &ranges[signal];
+            let positive = !signed(&constraint.c()[signal], field).is_negative();
+            let value = if positive == at_max { &range.max } else { &range.min };
+            let mut expression = HashMap::new();
+            expression.insert(*signal, BigInt::one());
+            if !value.is_zero() {
+                expression.insert(constant, field - value);
+            }
+            result.push_back(C::new(HashMap::new(), HashMap::new(), expression));
+        }
+        split += 1;
+    }
+    (result, split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{expression, field, linear, quadratic, storage};
+
+    fn range(min: i64, max: i64) -> Range {
+        Range { min: BigInt::from(min), max: BigInt::from(max) }
+    }
+
+    // b1, b2, b3 boolean
+    fn booleans() -> ConstraintStorage {
+        storage((1..4).map(|b| quadratic(&[(b, 1)], &[(b, 1), (0, -1)], &[])).collect())
+    }
+
+    #[test]
+    fn sum_of_bits_is_bounded() {
+        let field = field();
+        let mut definitions = LinkedList::new();
+        // s = b1 + 2*b2 + 4*b3, t = s + 1 and u = 200*b1 + 200*b2 wraps around p
+        definitions.push_back(linear(&[(4, 1), (1, -1), (2, -2), (3, -4)]));
+        definitions.push_back(linear(&[(5, -1), (4, 1), (0, 1)]));
+        definitions.push_back(linear(&[(6, 1), (1, -200), (2, -200)]));
+        let ranges = infer_ranges(&definitions, &booleans(), &field);
+        for b in 1..4 {
+            assert_eq!(ranges[&b], range(0, 1));
+        }
+        assert_eq!(ranges[&4], range(0, 7));
+        assert_eq!(ranges[&5], range(1, 8));
+        assert!(!ranges.contains_key(&6));
+        assert!(excludes_zero(&expression(&[(5, 1)]), &ranges, &field));
+        assert!(!excludes_zero(&expression(&[(4, 1)]), &ranges, &field));
+    }
+
+    #[test]
+    fn sums_at_a_bound_are_split() {
+        let field = field();
+        let ranges = infer_ranges(&LinkedList::new(), &booleans(), &field);
+        let mut constraints = LinkedList::new();
+        // b1 + b2 + b3 = 0, b1 + b2 = 2 and b1 = b2, which does not force its terms
+        constraints.push_back(linear(&[(1, 1), (2, 1), (3, 1)]));
+        constraints.push_back(linear(&[(1, 1), (2, 1), (0, -2)]));
+        constraints.push_back(linear(&[(1, 1), (2, -1)]));
+        let (result, split) = split_bounded_sums(constraints, &ranges, &field);
+        assert_eq!(split, 2);
+        let result: Vec<_> = result.iter().map(|constraint| constraint.c().clone()).collect();
+        assert_eq!(
+            result,
+            vec![
+                expression(&[(1, 1)]),
+                expression(&[(2, 1)]),
+                expression(&[(3, 1)]),
+                expression(&[(1, 1), (0, -1)]),
+                expression(&[(2, 1), (0, -1)]),
+                expression(&[(1, 1), (2, -1)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SignalRole {
+    Constant,
+    Output,
+    Input,
+    Private,
+}
+
+impl SignalRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignalRole::Constant => "constant",
+            SignalRole::Output => "output",
+            SignalRole::Input => "input",
+            SignalRole::Private => "private",
+        }
+    }
+}
+
+pub struct SignalInfo {
+    pub name: String,
+    pub role: SignalRole,
+}
+
+// label of the signal -> name and role given by the circuit
+pub type SignalTable = HashMap<usize, SignalInfo>;
+
+pub fn signal_name(table: &SignalTable, signal: usize) -> String {
+    match table.get(&signal) {
+        Some(info) => info.name.clone(),
+        None => format!("#{}", signal),
+    }
+}
+
+pub fn signal_role(table: &SignalTable, signal: usize) -> SignalRole {
+    table.get(&signal).map_or(SignalRole::Private, |info| info.role)
+}
@@ -0,0 +1,71 @@
+use crate::constraint_simplification::build_non_linear_signal_map;
+use std::collections::{BTreeSet, HashSet};
+use super::{ConstraintStorage, C};
+
+/*
+    Cone of influence of a set of outputs over the signal-constraint graph. Starting
+    from the outputs, every constraint in which a signal of the cone appears is taken
+    and its signals join the cone. A constraint that only uses a signal in A or B still
+    restricts it (in*out = 0 fixes out in an IsZero gadget), so the side of the signal
+    is not looked at. The inputs and the outputs that were not selected are the
+    boundary: they are kept in the slice but the search does not go through them.
+*/
+
+pub struct Slice {
+    // constraint ids of the storage in the cone
+    pub constraints: BTreeSet<usize>,
+    // signals of the constraints of the cone
+    pub signals: BTreeSet<usize>,
+    // boundary signals reached from the outputs
+    pub boundary: BTreeSet<usize>,
+}
+
+pub fn cone_of_influence(storage: &ConstraintStorage, outputs: &BTreeSet<usize>, boundary: &HashSet<usize>) -> Slice {
+    let map = build_non_linear_signal_map(storage);
+    let constant = C::constant_coefficient();
+    let mut slice = Slice { constraints: BTreeSet::new(), signals: outputs.clone(), boundary: BTreeSet::new() };
+    let mut pending: Vec<usize> = outputs.iter().cloned().collect();
+    while let Some(signal) = pending.pop() {
+        for c_id in map.get(&signal).into_iter().flatten() {
+            if slice.constraints.contains(c_id) {
+                continue;
+            }
+            let constraint = storage.read_constraint(*c_id).unwrap();
+            slice.constraints.insert(*c_id);
+            for other in C::take_cloned_signals(&constraint) {
+                if other == constant || !slice.signals.insert(other) {
+                    continue;
+                }
+                if boundary.contains(&other) {
+                    slice.boundary.insert(other);
+                } else {
+                    pending.push(other);
+                }
+            }
+        }
+    }
+    slice
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{linear, quadratic, storage};
+
+    #[test]
+    fn slice_keeps_the_constraints_of_an_is_zero_gadget() {
+        // in*inv = 1 - out, in*out = 0, out + y = z, w*w = v
+        let circuit = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(0, 1), (3, -1)]),
+            quadratic(&[(1, 1)], &[(3, 1)], &[]),
+            linear(&[(3, 1), (4, 1), (5, -1)]),
+            quadratic(&[(6, 1)], &[(6, 1)], &[(7, 1)]),
+        ]);
+        let outputs: BTreeSet<usize> = [3].iter().cloned().collect();
+        let inputs: HashSet<usize> = [1, 4].iter().cloned().collect();
+        let slice = cone_of_influence(&circuit, &outputs, &inputs);
+        assert_eq!(slice.constraints, [0, 1, 2].iter().cloned().collect());
+        assert_eq!(slice.boundary, [1, 4].iter().cloned().collect());
+        assert!(!slice.signals.contains(&6));
+    }
+}
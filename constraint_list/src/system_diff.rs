@@ -0,0 +1,237 @@
+use crate::signal_table::{signal_name, SignalTable};
+use crate::SignalMap;
+use circom_algebra::algebra::HashConstraint;
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::Zero;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use super::{ConstraintStorage, C, S};
+
+/*
+    Differences between a system and the result of optimizing it. The original
+    constraints are rewritten with the log of substitutions and matched (modulo
+    normalization) against the optimized ones, so every original constraint is either
+    kept, rewritten or removed, and the optimized constraints that match none of them
+    are the ones deduced by the simplification.
+*/
+
+pub struct RemovedSignal {
+    pub signal: usize,
+    // expression that replaced the signal, None when it was dropped without substitution
+    pub replacement: Option<HashMap<usize, BigInt>>,
+}
+
+pub struct RewrittenConstraint {
+    // id in the original system
+    pub original: usize,
+    // id in the optimized system
+    pub optimized: usize,
+    // the constraint went from quadratic to linear (or the other way)
+    pub changed_shape: bool,
+}
+
+#[derive(Default)]
+pub struct SystemDiff {
+    pub removed_signals: Vec<RemovedSignal>,
+    // original label -> label in the optimized system, only for the labels that change
+    pub renamed_signals: Vec<(usize, usize)>,
+    pub unchanged_constraints: Vec<(usize, usize)>,
+    pub removed_constraints: Vec<usize>,
+    pub rewritten_constraints: Vec<RewrittenConstraint>,
+    pub deduced_constraints: Vec<usize>,
+}
+
+fn signals_of(storage: &ConstraintStorage) -> BTreeSet<usize> {
+    let mut signals = BTreeSet::new();
+    for c_id in storage.get_ids() {
+        signals.extend(C::take_cloned_signals(&storage.read_constraint(c_id).unwrap()));
+    }
+    signals
+}
+
+// The log is applied until no substituted signal is left, since a substitution may
+// mention signals that are substituted later on. The log is indexed by the substituted
+// signal, the first substitution of a signal is the one applied.
+fn apply_log(constraint: &mut C, substitutions: &HashMap<usize, &S>, field: &BigInt) {
+    for _ in 0..=substitutions.len() {
+        let signals = C::take_cloned_signals(constraint);
+        let mut applied = false;
+        for signal in signals {
+            if let Some(substitution) = substitutions.get(&signal) {
+                C::apply_substitution(constraint, substitution, field);
+                applied = true;
+            }
+        }
+        if !applied {
+            return;
+        }
+    }
+}
+
+pub fn diff(
+    original: &ConstraintStorage,
+    optimized: &ConstraintStorage,
+    log: &[S],
+    signal_map: &SignalMap,
+    field: &BigInt,
+) -> SystemDiff {
+    let mut diff = SystemDiff::default();
+    let mut substitutions: HashMap<usize, &S> = HashMap::new();
+    for substitution in log {
+        substitutions.entry(*substitution.from()).or_insert(substitution);
+    }
+
+    let remaining = signals_of(optimized);
+    let mut replacements = HashMap::new();
+    for substitution in log {
+        replacements.entry(*substitution.from()).or_insert_with(|| substitution.to().clone());
+    }
+    for signal in signals_of(original) {
+        if signal != C::constant_coefficient() && !remaining.contains(&signal) {
+            let replacement = replacements.remove(&signal);
+            diff.removed_signals.push(RemovedSignal { signal, replacement });
+        }
+    }
+    let renamed: BTreeMap<usize, usize> =
+        signal_map.iter().filter(|(from, to)| from != to).map(|(from, to)| (*from, *to)).collect();
+    diff.renamed_signals = renamed.into_iter().filter(|(from, _)| remaining.contains(from)).collect();
+
+    let mut pending: HashMap<HashConstraint, Vec<usize>> = HashMap::new();
+    for c_id in optimized.get_ids().into_iter().rev() {
+        let constraint = optimized.read_constraint(c_id).unwrap();
+        if !constraint.is_empty() {
+            pending.entry(C::get_hash_constraint(&constraint, field)).or_default().push(c_id);
+        }
+    }
+    let mut rewritten = Vec::new();
+    for c_id in original.get_ids() {
+        let constraint = original.read_constraint(c_id).unwrap();
+        if constraint.is_empty() {
+            continue;
+        }
+        let key = C::get_hash_constraint(&constraint, field);
+        if let Some(found) = pending.get_mut(&key).and_then(|ids| ids.pop()) {
+            diff.unchanged_constraints.push((c_id, found));
+        } else {
+            rewritten.push((c_id, constraint));
+        }
+    }
+    for (c_id, constraint) in rewritten {
+        let mut new_constraint = constraint.clone();
+        apply_log(&mut new_constraint, &substitutions, field);
+        let found = if new_constraint.is_empty() {
+            None
+        } else {
+            let key = C::get_hash_constraint(&new_constraint, field);
+            pending.get_mut(&key).and_then(|ids| ids.pop())
+        };
+        match found {
+            Some(found) => diff.rewritten_constraints.push(RewrittenConstraint {
+                original: c_id,
+                optimized: found,
+                changed_shape: C::is_linear(&constraint) != C::is_linear(&new_constraint),
+            }),
+            None => diff.removed_constraints.push(c_id),
+        }
+    }
+    diff.deduced_constraints = pending.into_values().flatten().collect();
+    diff.deduced_constraints.sort_unstable();
+    diff
+}
+
+fn coefficient_to_string(value: &BigInt, field: &BigInt) -> String {
+    // coefficients close to the prime are shown as negative numbers
+    let value = modular_arithmetic::add(value, &BigInt::zero(), field);
+    let negative = modular_arithmetic::prefix_sub(&value, field);
+    if negative.bits() < value.bits() {
+        format!("-{}", negative)
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn linear_expression_to_string(expression: &HashMap<usize, BigInt>, table: &SignalTable, field: &BigInt) -> String {
+    let constant = C::constant_coefficient();
+    let mut terms: Vec<(&usize, &BigInt)> = expression.iter().filter(|(_, v)| !v.is_zero()).collect();
+    terms.sort();
+    if terms.is_empty() {
+        return "0".to_string();
+    }
+    let terms: Vec<String> = terms
+        .into_iter()
+        .map(|(signal, value)| {
+            let value = coefficient_to_string(value, field);
+            if *signal == constant {
+                value
+            } else if value == "1" {
+                signal_name(table, *signal)
+            } else {
+                format!("{}*{}", value, signal_name(table, *signal))
+            }
+        })
+        .collect();
+    terms.join(" + ")
+}
+
+pub fn constraint_to_string(constraint: &C, table: &SignalTable, field: &BigInt) -> String {
+    if C::is_linear(constraint) {
+        format!("{} = 0", linear_expression_to_string(constraint.c(), table, field))
+    } else {
+        format!(
+            "({}) * ({}) = {}",
+            linear_expression_to_string(constraint.a(), table, field),
+            linear_expression_to_string(constraint.b(), table, field),
+            linear_expression_to_string(constraint.c(), table, field)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal_table::{SignalInfo, SignalRole};
+    use crate::test_utils::{expression, field, linear, quadratic, storage};
+    use crate::A;
+
+    #[test]
+    fn diff_with_the_substitution_log() {
+        let field = field();
+        // x*y = z, z = w, w*w = v optimized with w := z into x*y = z, z*z = v (v is
+        // renamed to 4) and the deduced x*x = y
+        let original = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(3, 1)]),
+            linear(&[(3, 1), (4, -1)]),
+            quadratic(&[(4, 1)], &[(4, 1)], &[(5, 1)]),
+        ]);
+        let optimized = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(3, 1)]),
+            quadratic(&[(3, 1)], &[(3, 1)], &[(5, 1)]),
+            quadratic(&[(1, 1)], &[(1, 1)], &[(2, 1)]),
+        ]);
+        let log = vec![S::new(4, A::Linear { coefficients: expression(&[(3, 1)]) }).unwrap()];
+        let signal_map: SignalMap = [(0, 0), (1, 1), (2, 2), (3, 3), (5, 4)].iter().cloned().collect();
+        let diff = diff(&original, &optimized, &log, &signal_map, &field);
+        assert_eq!(diff.removed_signals.len(), 1);
+        assert_eq!(diff.removed_signals[0].signal, 4);
+        assert_eq!(diff.removed_signals[0].replacement, Some(expression(&[(3, 1)])));
+        assert_eq!(diff.renamed_signals, vec![(5, 4)]);
+        assert_eq!(diff.unchanged_constraints, vec![(0, 0)]);
+        assert_eq!(diff.rewritten_constraints.len(), 1);
+        assert_eq!((diff.rewritten_constraints[0].original, diff.rewritten_constraints[0].optimized), (2, 1));
+        assert!(!diff.rewritten_constraints[0].changed_shape);
+        assert_eq!(diff.removed_constraints, vec![1]);
+        assert_eq!(diff.deduced_constraints, vec![2]);
+    }
+
+    #[test]
+    fn constraints_are_printed_with_negative_coefficients() {
+        let field = field();
+        let table: SignalTable = [(1, "x"), (2, "y")]
+            .iter()
+            .map(|(s, name)| (*s, SignalInfo { name: name.to_string(), role: SignalRole::Private }))
+            .collect();
+        let constraint = quadratic(&[(1, 2)], &[(2, 1), (0, -1)], &[(1, -1)]);
+        assert_eq!(constraint_to_string(&constraint, &table, &field), "(2*x) * (-1 + y) = -1*x");
+        assert_eq!(constraint_to_string(&linear(&[(1, 1), (2, -3)]), &table, &field), "x + -3*y = 0");
+    }
+}
@@ -0,0 +1,35 @@
+use circom_algebra::num_bigint::BigInt;
+use std::collections::HashMap;
+use super::{ConstraintStorage, C};
+
+pub const FIELD: &str = "257";
+
+pub fn field() -> BigInt {
+    BigInt::parse_bytes(FIELD.as_bytes(), 10).expect("generating the big int was not possible")
+}
+
+pub fn expression(coefficients: &[(usize, i64)]) -> HashMap<usize, BigInt> {
+    let field = field();
+    let mut expression = HashMap::new();
+    for (signal, value) in coefficients {
+        let value = ((BigInt::from(*value) % &field) + &field) % &field;
+        expression.insert(*signal, value);
+    }
+    expression
+}
+
+pub fn linear(coefficients: &[(usize, i64)]) -> C {
+    C::new(HashMap::new(), HashMap::new(), expression(coefficients))
+}
+
+pub fn quadratic(a: &[(usize, i64)], b: &[(usize, i64)], c: &[(usize, i64)]) -> C {
+    C::new(expression(a), expression(b), expression(c))
+}
+
+pub fn storage(constraints: Vec<C>) -> ConstraintStorage {
+    let mut storage = ConstraintStorage::new();
+    for constraint in constraints {
+        storage.add_constraint(constraint);
+    }
+    storage
+}
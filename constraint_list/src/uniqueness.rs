@@ -0,0 +1,166 @@
+use crate::degenerate_quadratics::allowed_values;
+use crate::non_zero::{infer_non_zero, proven_non_zero, Expression};
+use crate::range_analysis::{infer_ranges, SignalRanges};
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::Zero;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, LinkedList};
+use super::{ConstraintStorage, C};
+
+/*
+    Uniqueness checker in the style of Ecne. Starting from the inputs, a signal is
+    unique when a constraint in which every other signal is unique determines it:
+        linearly, when it only appears in C,
+        through a factor, A*B = C with the signal in A and B unique, when the
+        coefficient of the signal (a*B - c) is proven non-zero,
+        through a quadratic with a single root.
+    The signals that are left are not proven unique (the circuit may be underconstrained
+    or the propagation may not be strong enough), and the constraints in which they
+    appear that could not determine any signal are the ones that block them.
+*/
+
+pub struct NotUnique {
+    pub signal: usize,
+    pub constraints: Vec<usize>,
+}
+
+pub struct UniquenessReport {
+    pub unique: BTreeSet<usize>,
+    pub not_unique: Vec<NotUnique>,
+}
+
+struct Facts {
+    non_zero: HashSet<Expression>,
+    ranges: SignalRanges,
+}
+
+fn coefficient(expression: &HashMap<usize, BigInt>, signal: usize, field: &BigInt) -> BigInt {
+    expression.get(&signal).map_or_else(BigInt::zero, |value| modular_arithmetic::add(value, &BigInt::zero(), field))
+}
+
+// The constraint, with every signal but signal fixed, has a single solution for it
+fn determines(constraint: &C, signal: usize, facts: &Facts, field: &BigInt) -> bool {
+    let in_a = coefficient(constraint.a(), signal, field);
+    let in_b = coefficient(constraint.b(), signal, field);
+    let in_c = coefficient(constraint.c(), signal, field);
+    let other_side = match (in_a.is_zero(), in_b.is_zero()) {
+        (true, true) => return !in_c.is_zero(),
+        (false, true) => Some((&in_a, constraint.b())),
+        (true, false) => Some((&in_b, constraint.a())),
+        (false, false) => None,
+    };
+    match other_side {
+        // (a*x + A')*B' = c*x + C' is (a*B' - c)*x = C' - A'*B'
+        Some((factor, other)) => {
+            let mut linear_coefficient: HashMap<usize, BigInt> = other
+                .iter()
+                .map(|(s, value)| (*s, modular_arithmetic::mul(value, factor, field)))
+                .collect();
+            let constant = C::constant_coefficient();
+            let current = linear_coefficient.remove(&constant).unwrap_or_else(BigInt::zero);
+            linear_coefficient.insert(constant, modular_arithmetic::sub(&current, &in_c, field));
+            proven_non_zero(&linear_coefficient, &facts.non_zero, &facts.ranges, field)
+        }
+        None => match allowed_values(constraint, field) {
+            Some((form, values)) => form.len() == 1 && form[0].0 == signal && values.len() == 1,
+            None => false,
+        },
+    }
+}
+
+pub fn check_uniqueness(storage: &ConstraintStorage, inputs: &HashSet<usize>, signals: &BTreeSet<usize>, field: &BigInt) -> UniquenessReport {
+    let constant = C::constant_coefficient();
+    let mut linear = LinkedList::new();
+    let mut constraints = BTreeMap::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if C::is_empty(&constraint) {
+            continue;
+        }
+        if C::is_linear(&constraint) {
+            linear.push_back(constraint.clone());
+        }
+        constraints.insert(c_id, constraint);
+    }
+    let ranges = infer_ranges(&linear, storage, field);
+    let facts = Facts { non_zero: infer_non_zero(storage, &ranges, field), ranges };
+
+    let mut unique: BTreeSet<usize> = inputs.iter().cloned().collect();
+    unique.insert(constant);
+    let mut pending: BTreeMap<usize, C> = constraints.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut solved = Vec::new();
+        for (c_id, constraint) in &pending {
+            let mut unknown = C::take_cloned_signals(constraint).into_iter().filter(|s| !unique.contains(s));
+            match (unknown.next(), unknown.next()) {
+                (None, _) => solved.push(*c_id),
+                (Some(signal), None) if determines(constraint, signal, &facts, field) => {
+                    unique.insert(signal);
+                    solved.push(*c_id);
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+        for c_id in solved {
+            pending.remove(&c_id);
+        }
+    }
+
+    let mut not_unique = Vec::new();
+    for signal in signals {
+        if unique.contains(signal) {
+            continue;
+        }
+        let blocking = pending
+            .iter()
+            .filter(|(_, constraint)| C::take_cloned_signals(constraint).contains(signal))
+            .map(|(c_id, _)| *c_id)
+            .collect();
+        not_unique.push(NotUnique { signal: *signal, constraints: blocking });
+    }
+    unique.remove(&constant);
+    UniquenessReport { unique, not_unique }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{field, linear, quadratic, storage};
+
+    fn set<T: Clone + Ord>(values: &[T]) -> BTreeSet<T> {
+        values.iter().cloned().collect()
+    }
+
+    #[test]
+    fn inverse_and_linear_outputs_are_unique() {
+        let field = field();
+        // in*inv = 1, out = 3*in + inv and b*inv = 0, which gives b = 0 as inv is non-zero
+        let constraints = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(0, 1)]),
+            linear(&[(3, 1), (1, -3), (2, -1)]),
+            quadratic(&[(4, 1)], &[(2, 1)], &[]),
+        ]);
+        let inputs: HashSet<usize> = [1].iter().cloned().collect();
+        let report = check_uniqueness(&constraints, &inputs, &set(&[2, 3, 4]), &field);
+        assert_eq!(report.unique, set(&[1, 2, 3, 4]));
+        assert!(report.not_unique.is_empty());
+    }
+
+    #[test]
+    fn is_zero_gadget_is_not_proven_unique() {
+        let field = field();
+        // in*inv = 1 - out, in*out = 0: inv is free when in = 0
+        let constraints = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(0, 1), (3, -1)]),
+            quadratic(&[(1, 1)], &[(3, 1)], &[]),
+        ]);
+        let inputs: HashSet<usize> = [1].iter().cloned().collect();
+        let report = check_uniqueness(&constraints, &inputs, &set(&[2, 3]), &field);
+        assert_eq!(report.unique, set(&[1]));
+        let blocked: Vec<_> = report.not_unique.iter().map(|entry| (entry.signal, entry.constraints.clone())).collect();
+        assert_eq!(blocked, vec![(2, vec![0]), (3, vec![0, 1])]);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::groebner::{ideal_contains, GroebnerConfig};
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use circom_algebra::num_traits::{One, Zero};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use super::{ConstraintStorage, C};
+
+/*
+    Candidate equalities found in the witnesses. The signals whose values are related
+    by x = k*y + c in every witness (equal values with one or two witnesses, since any
+    two signals are related by some k and c in two points) or that have the same value
+    in several witnesses are candidates for a linear relation the simplification missed.
+    A candidate is only applied when it belongs to the ideal of the constraints around
+    its signals, checked with the bounded Groebner basis; the rest are reported.
+*/
+
+// constraints taken around the signals of a candidate for its proof
+const NEIGHBOURHOOD: usize = 16;
+// candidates proposed for each group of related signals: with few witnesses the signals
+// that are always 0 or 1 form huge groups, and every candidate costs a Groebner proof
+const MAX_GROUP_CANDIDATES: usize = 8;
+
+// signal = factor * base + offset, or signal = offset without a base
+pub struct Candidate {
+    pub signal: usize,
+    pub base: Option<usize>,
+    pub factor: BigInt,
+    pub offset: BigInt,
+}
+
+impl Candidate {
+    pub fn constraint(&self, field: &BigInt) -> C {
+        let mut expression = HashMap::new();
+        expression.insert(self.signal, BigInt::one());
+        if let Some(base) = self.base {
+            expression.insert(base, modular_arithmetic::prefix_sub(&self.factor, field));
+        }
+        if !self.offset.is_zero() {
+            expression.insert(C::constant_coefficient(), modular_arithmetic::prefix_sub(&self.offset, field));
+        }
+        C::new(HashMap::new(), HashMap::new(), expression)
+    }
+}
+
+pub struct CandidateReport {
+    pub proven: Vec<C>,
+    pub unproven: Vec<Candidate>,
+}
+
+// The values of the signal relative to the first two different ones: every signal
+// k*y + c has the same key as y
+fn affine_key(values: &[BigInt], field: &BigInt) -> Option<Vec<BigInt>> {
+    let first = &values[0];
+    let scale = values.iter().find(|v| *v != first)?;
+    let inverse = modular_arithmetic::div(&BigInt::one(), &modular_arithmetic::sub(scale, first, field), field).ok()?;
+    Some(values.iter().map(|v| modular_arithmetic::mul(&modular_arithmetic::sub(v, first, field), &inverse, field)).collect())
+}
+
+// The candidates and the number of them skipped by the limit of each group
+pub fn candidate_equalities(signals: &BTreeSet<usize>, witnesses: &[&BTreeMap<usize, BigInt>], field: &BigInt) -> (Vec<Candidate>, usize) {
+    let mut groups: BTreeMap<Vec<BigInt>, Vec<(usize, Vec<BigInt>)>> = BTreeMap::new();
+    let mut constants: BTreeMap<BigInt, usize> = BTreeMap::new();
+    let mut candidates = Vec::new();
+    let mut skipped = 0;
+    for signal in signals {
+        let values: Option<Vec<BigInt>> = witnesses.iter().map(|witness| witness.get(signal).cloned()).collect();
+        let values = match values {
+            Some(values) if !values.is_empty() => values,
+            _ => continue,
+        };
+        if witnesses.len() > 1 && values.iter().all(|v| *v == values[0]) {
+            let proposed = constants.entry(values[0].clone()).or_insert(0);
+            if *proposed < MAX_GROUP_CANDIDATES {
+                *proposed += 1;
+                candidates.push(Candidate { signal: *signal, base: None, factor: BigInt::zero(), offset: values[0].clone() });
+            } else {
+                skipped += 1;
+            }
+            continue;
+        }
+        let key = if witnesses.len() < 3 { Some(values.clone()) } else { affine_key(&values, field) };
+        if let Some(key) = key {
+            groups.entry(key).or_default().push((*signal, values));
+        }
+    }
+    for (_, members) in groups {
+        let (base, base_values) = &members[0];
+        // the first index where the base changes, if it does
+        let change = base_values.iter().position(|v| *v != base_values[0]);
+        skipped += members.len().saturating_sub(MAX_GROUP_CANDIDATES + 1);
+        for (signal, values) in members[1..].iter().take(MAX_GROUP_CANDIDATES) {
+            let factor = match change {
+                Some(i) => modular_arithmetic::div(
+                    &modular_arithmetic::sub(&values[i], &values[0], field),
+                    &modular_arithmetic::sub(&base_values[i], &base_values[0], field),
+                    field,
+                )
+                .unwrap(),
+                None => BigInt::one(),
+            };
+            let offset = modular_arithmetic::sub(&values[0], &modular_arithmetic::mul(&factor, &base_values[0], field), field);
+            candidates.push(Candidate { signal: *signal, base: Some(*base), factor, offset });
+        }
+    }
+    (candidates, skipped)
+}
+
+// The constraints connected to the signals, closest first
+fn neighbourhood(storage: &ConstraintStorage, occurrences: &BTreeMap<usize, BTreeSet<usize>>, signals: &[usize]) -> ConstraintStorage {
+    let mut taken = BTreeSet::new();
+    let mut visited: HashSet<usize> = signals.iter().cloned().collect();
+    let mut frontier: Vec<usize> = signals.to_vec();
+    while !frontier.is_empty() && taken.len() < NEIGHBOURHOOD {
+        let mut next = Vec::new();
+        for signal in frontier {
+            for c_id in occurrences.get(&signal).into_iter().flatten() {
+                if taken.len() == NEIGHBOURHOOD || !taken.insert(*c_id) {
+                    continue;
+                }
+                for other in C::take_cloned_signals(&storage.read_constraint(*c_id).unwrap()) {
+                    if other != C::constant_coefficient() && visited.insert(other) {
+                        next.push(other);
+                    }
+                }
+            }
+        }
+        next.sort_unstable();
+        frontier = next;
+    }
+    let mut cluster = ConstraintStorage::new();
+    for c_id in taken {
+        cluster.add_constraint(storage.read_constraint(c_id).unwrap());
+    }
+    cluster
+}
+
+pub fn prove_candidates(storage: &ConstraintStorage, candidates: Vec<Candidate>, groebner: &GroebnerConfig, field: &BigInt) -> CandidateReport {
+    let mut occurrences: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        for signal in C::take_cloned_signals(&constraint) {
+            occurrences.entry(signal).or_default().insert(c_id);
+        }
+    }
+    let mut report = CandidateReport { proven: Vec::new(), unproven: Vec::new() };
+    for candidate in candidates {
+        let constraint = candidate.constraint(field);
+        let signals: Vec<usize> = std::iter::once(candidate.signal).chain(candidate.base).collect();
+        let cluster = neighbourhood(storage, &occurrences, &signals);
+        if ideal_contains(&cluster, &constraint, field, groebner) {
+            report.proven.push(constraint);
+        } else {
+            report.unproven.push(candidate);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{expression, field, quadratic, storage};
+
+    fn witness(values: &[(usize, i64)]) -> BTreeMap<usize, BigInt> {
+        values.iter().map(|(signal, value)| (*signal, BigInt::from(*value))).collect()
+    }
+
+    fn groebner() -> GroebnerConfig {
+        GroebnerConfig { max_constraints: 8, max_degree: 3, time_budget: std::time::Duration::from_secs(10), max_pairs: None }
+    }
+
+    #[test]
+    fn affine_relations_in_three_witnesses() {
+        let field = field();
+        // x, 2x + 5, a constant 4 and a signal unrelated to x
+        let first = witness(&[(1, 1), (2, 7), (3, 4), (4, 1)]);
+        let second = witness(&[(1, 2), (2, 9), (3, 4), (4, 5)]);
+        let third = witness(&[(1, 3), (2, 11), (3, 4), (4, 2)]);
+        let signals: BTreeSet<usize> = (1..5).collect();
+        let (candidates, skipped) = candidate_equalities(&signals, &[&first, &second, &third], &field);
+        assert_eq!(skipped, 0);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].constraint(&field).c(), &expression(&[(3, 1), (0, -4)]));
+        assert_eq!(candidates[1].signal, 2);
+        assert_eq!(candidates[1].base, Some(1));
+        assert_eq!(candidates[1].constraint(&field).c(), &expression(&[(2, 1), (1, -2), (0, -5)]));
+    }
+
+    #[test]
+    fn only_the_candidates_in_the_ideal_are_proven() {
+        let field = field();
+        // x*inv = 1, x*(y - z) = 0 prove y = z but not x = 5
+        let constraints = storage(vec![
+            quadratic(&[(1, 1)], &[(2, 1)], &[(0, 1)]),
+            quadratic(&[(1, 1)], &[(3, 1), (4, -1)], &[]),
+        ]);
+        let candidates = vec![
+            Candidate { signal: 4, base: Some(3), factor: BigInt::one(), offset: BigInt::zero() },
+            Candidate { signal: 1, base: None, factor: BigInt::zero(), offset: BigInt::from(5) },
+        ];
+        let report = prove_candidates(&constraints, candidates, &groebner(), &field);
+        assert_eq!(report.proven.len(), 1);
+        assert_eq!(report.proven[0].c(), &expression(&[(4, 1), (3, -1)]));
+        assert_eq!(report.unproven.len(), 1);
+        assert_eq!(report.unproven[0].signal, 1);
+    }
+
+    #[test]
+    fn equal_values_of_a_single_witness_are_capped() {
+        let field = field();
+        // 20 signals equal to 0 and 3 equal to 5
+        let mut values: Vec<(usize, i64)> = (1..21).map(|signal| (signal, 0)).collect();
+        values.extend([(21, 5), (22, 5), (23, 5)]);
+        let only = witness(&values);
+        let signals: BTreeSet<usize> = (1..24).collect();
+        let (candidates, skipped) = candidate_equalities(&signals, &[&only], &field);
+        assert_eq!(candidates.len(), MAX_GROUP_CANDIDATES + 2);
+        assert_eq!(skipped, 20 - 1 - MAX_GROUP_CANDIDATES);
+        assert!(candidates.iter().all(|candidate| candidate.base == Some(1) || candidate.base == Some(21)));
+    }
+}
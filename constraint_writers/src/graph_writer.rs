@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+pub struct GraphNode {
+    pub id: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+}
+
+pub struct Graph {
+    pub name: String,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Graph {
+    pub fn new(name: &str) -> Graph {
+        Graph { name: name.to_string(), nodes: Vec::new(), edges: Vec::new() }
+    }
+
+    // attribute keys of the nodes, in order of appearance
+    fn attribute_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for node in &self.nodes {
+            for (key, _) in &node.attributes {
+                if !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+        keys
+    }
+
+    pub fn write_dot(&self, out: &str) -> Result<(), ()> {
+        let file = File::create(out).map_err(|_err| {})?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "graph \"{}\" {{", escape_dot(&self.name)).map_err(|_err| {})?;
+        for node in &self.nodes {
+            let attributes: Vec<String> = node
+                .attributes
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape_dot(value)))
+                .collect();
+            writeln!(writer, "  \"{}\" [{}];", escape_dot(&node.id), attributes.join(", "))
+                .map_err(|_err| {})?;
+        }
+        for edge in &self.edges {
+            writeln!(writer, "  \"{}\" -- \"{}\";", escape_dot(&edge.source), escape_dot(&edge.target))
+                .map_err(|_err| {})?;
+        }
+        writeln!(writer, "}}").map_err(|_err| {})?;
+        writer.flush().map_err(|_err| {})
+    }
+
+    pub fn write_graphml(&self, out: &str) -> Result<(), ()> {
+        let file = File::create(out).map_err(|_err| {})?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").map_err(|_err| {})?;
+        writeln!(writer, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">").map_err(|_err| {})?;
+        for key in self.attribute_keys() {
+            writeln!(
+                writer,
+                "  <key id=\"{0}\" for=\"node\" attr.name=\"{0}\" attr.type=\"string\"/>",
+                escape_xml(&key)
+            )
+            .map_err(|_err| {})?;
+        }
+        writeln!(writer, "  <graph id=\"{}\" edgedefault=\"undirected\">", escape_xml(&self.name))
+            .map_err(|_err| {})?;
+        for node in &self.nodes {
+            writeln!(writer, "    <node id=\"{}\">", escape_xml(&node.id)).map_err(|_err| {})?;
+            for (key, value) in &node.attributes {
+                writeln!(writer, "      <data key=\"{}\">{}</data>", escape_xml(key), escape_xml(value))
+                    .map_err(|_err| {})?;
+            }
+            writeln!(writer, "    </node>").map_err(|_err| {})?;
+        }
+        for edge in &self.edges {
+            writeln!(
+                writer,
+                "    <edge source=\"{}\" target=\"{}\"/>",
+                escape_xml(&edge.source),
+                escape_xml(&edge.target)
+            )
+            .map_err(|_err| {})?;
+        }
+        writeln!(writer, "  </graph>").map_err(|_err| {})?;
+        writeln!(writer, "</graphml>").map_err(|_err| {})?;
+        writer.flush().map_err(|_err| {})
+    }
+}
@@ -1,6 +1,8 @@
 pub mod debug_writer;
+pub mod graph_writer;
 pub mod json_writer;
 pub mod log_writer;
+pub mod r1cs_reader;
 pub mod r1cs_writer;
 pub mod sym_writer;
 
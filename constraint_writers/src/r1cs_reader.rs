@@ -0,0 +1,109 @@
+use circom_algebra::num_bigint::{BigInt, Sign};
+use std::collections::HashMap;
+use std::fs;
+
+const MAGIC: &[u8] = b"r1cs";
+const HEADER_TYPE: usize = 1;
+const CONSTRAINT_TYPE: usize = 2;
+const WIRE2LABEL_TYPE: usize = 3;
+
+type LinearCombination = HashMap<usize, BigInt>;
+
+pub struct R1CSData {
+    pub field: BigInt,
+    pub total_wires: usize,
+    pub public_outputs: usize,
+    pub public_inputs: usize,
+    pub private_inputs: usize,
+    pub number_of_labels: usize,
+    // A, B and C of the constraints A*B - C = 0 over the wires
+    pub constraints: Vec<(LinearCombination, LinearCombination, LinearCombination)>,
+    pub wire_to_label: Vec<usize>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8], ()> {
+        let end = self.position.checked_add(size).ok_or(())?;
+        let bytes = self.bytes.get(self.position..end).ok_or(())?;
+        self.position = end;
+        Result::Ok(bytes)
+    }
+    fn read_bigint(&mut self, size: usize) -> Result<BigInt, ()> {
+        Result::Ok(BigInt::from_bytes_le(Sign::Plus, self.take(size)?))
+    }
+    fn read_u32(&mut self) -> Result<usize, ()> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Result::Ok(u32::from_le_bytes(bytes) as usize)
+    }
+    fn read_u64(&mut self) -> Result<usize, ()> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Result::Ok(u64::from_le_bytes(bytes) as usize)
+    }
+    fn read_linear_combination(&mut self, field_size: usize) -> Result<LinearCombination, ()> {
+        let mut linear_combination = HashMap::new();
+        for _ in 0..self.read_u32()? {
+            let wire = self.read_u32()?;
+            let factor = self.read_bigint(field_size)?;
+            linear_combination.insert(wire, factor);
+        }
+        Result::Ok(linear_combination)
+    }
+}
+
+// Reads the sections written by R1CSWriter, which can come in any order (the writer
+// puts the constraints before the header). None when the file is not a valid R1CS.
+pub fn read_r1cs(file: &str) -> Option<R1CSData> {
+    let bytes = fs::read(file).ok()?;
+    parse_r1cs(&bytes).ok()
+}
+
+fn parse_r1cs(bytes: &[u8]) -> Result<R1CSData, ()> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(4)? != MAGIC {
+        return Result::Err(());
+    }
+    let _version = reader.read_u32()?;
+    let number_of_sections = reader.read_u32()?;
+    let mut sections = HashMap::new();
+    for _ in 0..number_of_sections {
+        let section_type = reader.read_u32()?;
+        let size = reader.read_u64()?;
+        sections.insert(section_type, reader.take(size)?);
+    }
+
+    let mut header = Reader { bytes: sections.get(&HEADER_TYPE).ok_or(())?, position: 0 };
+    let field_size = header.read_u32()?;
+    let mut data = R1CSData {
+        field: header.read_bigint(field_size)?,
+        total_wires: header.read_u32()?,
+        public_outputs: header.read_u32()?,
+        public_inputs: header.read_u32()?,
+        private_inputs: header.read_u32()?,
+        number_of_labels: header.read_u64()?,
+        constraints: Vec::new(),
+        wire_to_label: Vec::new(),
+    };
+    let number_of_constraints = header.read_u32()?;
+
+    let mut constraints = Reader { bytes: sections.get(&CONSTRAINT_TYPE).ok_or(())?, position: 0 };
+    for _ in 0..number_of_constraints {
+        let a = constraints.read_linear_combination(field_size)?;
+        let b = constraints.read_linear_combination(field_size)?;
+        let c = constraints.read_linear_combination(field_size)?;
+        data.constraints.push((a, b, c));
+    }
+    if let Some(bytes) = sections.get(&WIRE2LABEL_TYPE) {
+        let mut labels = Reader { bytes, position: 0 };
+        for _ in 0..data.total_wires {
+            data.wire_to_label.push(labels.read_u64()?);
+        }
+    }
+    Result::Ok(data)
+}
@@ -55,7 +55,14 @@ where
     let (stream, bytes) = bigint_as_bytes(&non_zero_factors, 4);
     size += bytes;
     block.extend_from_slice(&stream);
-    for (id, factor) in linear_combination {
+    // the ids are little endian, they are written by increasing value so the
+    // output does not depend on the order of the map
+    let mut factors: Vec<(&T, &BigInt)> = linear_combination.iter().collect();
+    factors.sort_by(|(id_0, _), (id_1, _)| {
+        let (id_0, id_1) = (id_0.as_ref(), id_1.as_ref());
+        id_0.len().cmp(&id_1.len()).then_with(|| id_0.iter().rev().cmp(id_1.iter().rev()))
+    });
+    for (id, factor) in factors {
         let (stream, bytes) = into_format(id.as_ref(), 4);
         size += bytes;
         block.extend_from_slice(&stream);
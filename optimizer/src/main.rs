@@ -1,7 +1,17 @@
-use std::{fs, collections::{HashMap, LinkedList, HashSet, BTreeMap}, env, time::SystemTime, io::Write};
+use std::{fs, collections::{HashMap, LinkedList, HashSet, BTreeMap, BTreeSet}, env, time::SystemTime, io::Write};
+use constraint_list::cluster_stats;
 use constraint_list::constraint_simplification;
+use constraint_list::graph_export;
+use constraint_list::lint;
+use constraint_list::slicing;
+use constraint_list::signal_table::{self, SignalInfo, SignalRole, SignalTable};
+use constraint_list::system_diff;
+use constraint_list::uniqueness;
 use num_bigint_dig::BigInt;
-use circom_algebra::algebra::Constraint;
+use circom_algebra::algebra::{ArithmeticExpression, Constraint, Substitution};
+use circom_algebra::modular_arithmetic;
+use constraint_writers::r1cs_reader;
+use constraint_writers::sym_writer::{SymElem, SymFile};
 
 
 
@@ -68,7 +78,7 @@ fn  read_number_equalities( line : Option<&str>) -> Option<usize> {
     return Some(num_equalities);
 }
 
-fn read_witness(file : &String, signal_to_label : HashMap<&str, usize>) -> BTreeMap<usize,BigInt>{
+fn read_witness(file : &String, signal_to_label : &HashMap<String, usize>) -> BTreeMap<usize,BigInt>{
     let mut hmap = BTreeMap::new();
     let contents = fs::read_to_string(file).expect("File not found");
     let mut opt_line = contents.lines();
@@ -90,14 +100,17 @@ fn read_witness(file : &String, signal_to_label : HashMap<&str, usize>) -> BTree
     hmap
 }
 
-fn main() {
-    // --snip--
-    let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
-    let simplified_or_not = & args[2];
-    let apply_non_linear_simplification : bool = simplified_or_not.eq("simplified");
-    println!("In file {}", filename);
+struct Circuit {
+    constraints: LinkedList<Constraint<usize>>,
+    forbidden: HashSet<usize>,
+    signals: SignalTable,
+    labels: HashMap<String, usize>,
+    field: BigInt,
+    num_circuit_variables: usize,
+}
 
+fn read_circuit(filename : &str) -> Option<Circuit> {
+    println!("In file {}", filename);
     let contents = fs::read_to_string(filename)
         .expect("Something went wrong reading the file");
     /*let mut opt1_line = contents.lines();
@@ -111,22 +124,23 @@ fn main() {
         check_line = opt1_line.next();
     }*/
     let mut forb = HashSet::new();
+    let mut signals = SignalTable::new();
     let mut opt_line = contents.lines();
     let zokrates_line = opt_line.next();
-    if !read_auto_generated(zokrates_line) {return;}
+    if !read_auto_generated(zokrates_line) {return None;}
 
     let circuit_variables_line = opt_line.next();
     let mut num_circuit_variables = 0;
     match read_circuit_variables(circuit_variables_line){
         Some(value) => {num_circuit_variables = value;},
-        None => {return;},
+        None => {return None;},
     }
 
     let number_equalities_line = opt_line.next();
     let mut num_equalities = 0;
     match read_number_equalities(number_equalities_line){
         Some(value) => {num_equalities = value;},
-        None => {return;},
+        None => {return None;},
     }
 
     println!("Number of circuit variables and equalities is {} and {}, respesctively.", num_circuit_variables, num_equalities);
@@ -144,10 +158,15 @@ fn main() {
             let varstr : &str = process_declare_const(line);
             if varstr.eq("~one"){
                 varhm.insert(varstr,0);
+                signals.insert(0, SignalInfo { name: varstr.to_string(), role: SignalRole::Constant });
             }
             else if !varstr.eq("~prime"){
                 if varstr.contains("out") {
                     forb.insert(label);
+                    signals.insert(label, SignalInfo { name: varstr.to_string(), role: SignalRole::Output });
+                }
+                else {
+                    signals.insert(label, SignalInfo { name: varstr.to_string(), role: SignalRole::Private });
                 }
                 varhm.insert(varstr, label);
                 label = label + 1;
@@ -184,22 +203,864 @@ fn main() {
     /* Let us ignore the last line " )) " */
     opt_line.next();
 
+    println!("TERMINA DE LEER TODAS LAS CONSTRAINTS");
+    let labels = varhm.iter().map(|(name, label)| (name.to_string(), *label)).collect();
+    Some(Circuit { constraints: ll, forbidden: forb, signals, labels, field: prime_field, num_circuit_variables })
+}
 
+fn main() {
+    // --snip--
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 2 && args[1].eq("lint") {
+        lint_circuit(&args[2]);
+        return;
+    }
+    if args.len() > 2 && args[1].eq("unique") {
+        check_uniqueness(&args[2], &args[3..]);
+        return;
+    }
+    if args.len() > 2 && args[1].eq("diff") {
+        let artifacts = args.get(3).map_or(".", |dir| dir.as_str());
+        diff_circuit(&args[2], artifacts);
+        return;
+    }
+    if args.len() > 4 && args[1].eq("specialize") {
+        specialize_circuit(&args[2], &args[3], &args[4], &args[5..]);
+        return;
+    }
+    if args.len() > 2 && args[1].eq("slice") {
+        slice_circuit(&args[2], &args[3..]);
+        return;
+    }
+    if args.len() > 5 && args[1].eq("link") {
+        link_circuits(&args[2], &args[3], &args[4], &args[5], &args[6..]);
+        return;
+    }
+    if args.len() > 3 && args[1].eq("graph") {
+        export_graph(&args[2], &args[3], &args[4..]);
+        return;
+    }
+    let filename = &args[1];
+    let simplified_or_not = & args[2];
+    let apply_non_linear_simplification : bool = simplified_or_not.eq("simplified");
+    let circuit = match read_circuit(filename) {
+        Some(circuit) => circuit,
+        None => {return;},
+    };
 
-    let max_signal = num_circuit_variables;
-    let no_labels = num_circuit_variables;
-
-    println!("TERMINA DE LEER TODAS LAS CONSTRAINTS");
+    let max_signal = circuit.num_circuit_variables;
+    let no_labels = circuit.num_circuit_variables;
 
     let reading_witness = & args[3];
-    let witness = read_witness(reading_witness, varhm);
-    println!("PUBLICAS: {}", forb.len());        
+    let witness = read_witness(reading_witness, &circuit.labels);
+    println!("PUBLICAS: {}", circuit.forbidden.len());        
     let now = SystemTime::now();
-    generate_storage_and_simplify(ll, forb, no_labels, max_signal,  prime_field, apply_non_linear_simplification, witness);
+    let config = match read_simplification_config(apply_non_linear_simplification, &args[4..], &circuit.labels) {
+        Some(config) => config,
+        None => {return;},
+    };
+    generate_storage_and_simplify(circuit.constraints, circuit.forbidden, no_labels, max_signal,  circuit.field, &config, witness);
     let dur = now.elapsed().unwrap().as_millis();
     println!("SIMPLIFICATION  was performed in {} ms", dur);
  }
 
+// reads an R1CS file, with the names of the sym file next to it when there is one
+// (otherwise #wire); the labels of the signals are the wires
+fn read_r1cs_circuit(filename : &str) -> Option<Circuit> {
+    println!("In file {}", filename);
+    let data = match r1cs_reader::read_r1cs(filename) {
+        Some(data) => data,
+        None => {
+            println!("ERROR. {} is not a valid R1CS file", filename);
+            return None;
+        }
+    };
+    let mut names = HashMap::new();
+    let sym = format!("{}.sym", filename.trim_end_matches(".r1cs"));
+    for line in fs::read_to_string(sym).unwrap_or_default().lines() {
+        let fields: Vec<&str> = line.splitn(4, ',').collect();
+        if let [_, wire, _, name] = fields.as_slice() {
+            if let Ok(wire) = wire.parse::<usize>() {
+                names.entry(wire).or_insert_with(|| name.to_string());
+            }
+        }
+    }
+    let mut forbidden = HashSet::new();
+    let mut signals = SignalTable::new();
+    let mut labels = HashMap::new();
+    let inputs_end = 1 + data.public_outputs + data.public_inputs + data.private_inputs;
+    for wire in 0..data.total_wires {
+        let role = if wire == 0 {
+            SignalRole::Constant
+        } else if wire <= data.public_outputs {
+            forbidden.insert(wire);
+            SignalRole::Output
+        } else if wire < inputs_end {
+            forbidden.insert(wire);
+            SignalRole::Input
+        } else {
+            SignalRole::Private
+        };
+        let name = match names.remove(&wire) {
+            Some(name) => name,
+            None if wire == 0 => "~one".to_string(),
+            None => format!("#{}", wire),
+        };
+        labels.insert(name.clone(), wire);
+        signals.insert(wire, SignalInfo { name, role });
+    }
+    let mut constraints = LinkedList::new();
+    for (a, b, c) in data.constraints {
+        constraints.push_back(Constraint::new(a, b, c));
+    }
+    Some(Circuit { constraints, forbidden, signals, labels, field: data.field, num_circuit_variables: data.total_wires })
+}
+
+fn read_input_circuit(filename : &str) -> Option<Circuit> {
+    if filename.ends_with(".r1cs") {
+        read_r1cs_circuit(filename)
+    } else {
+        read_circuit(filename)
+    }
+}
+
+// the witness of an R1CS file is a json array with the value of each wire
+fn read_input_witness(file : &String, signal_to_label : &HashMap<String, usize>) -> Option<BTreeMap<usize, BigInt>> {
+    if !file.ends_with(".json") {
+        return Some(read_witness(file, signal_to_label));
+    }
+    let mut witness = BTreeMap::new();
+    for (wire, value) in read_json(file)?.as_array()?.iter().enumerate() {
+        let value = match value {
+            serde_json::Value::String(value) => value.parse().ok()?,
+            value => value.to_string().parse().ok()?,
+        };
+        witness.insert(wire, value);
+    }
+    Some(witness)
+}
+
+// optimizer link <file> <witness> <file> <witness> [--connect OUT IN]... [flags]
+// the files are ZoKrates dumps or R1CS files (with a json witness). OUT is a signal of
+// the first circuit and IN one of the second, the flags are the ones of the
+// simplification; writes a.r1cs, witness.json, a.sym and roles.json of the composition
+fn link_circuits(first_file : &str, first_witness : &String, second_file : &str, second_witness : &String, options : &[String]) {
+    let mut connections = Vec::new();
+    let mut flags = Vec::new();
+    let mut it = options.iter();
+    while let Some(option) = it.next() {
+        if option != "--connect" {
+            flags.push(option.clone());
+            continue;
+        }
+        match (it.next(), it.next()) {
+            (Some(output), Some(input)) => connections.push((output.clone(), input.clone())),
+            _ => {
+                println!("ERROR. Expected an output and an input after --connect");
+                return;
+            }
+        }
+    }
+    let (first, second) = match (read_input_circuit(first_file), read_input_circuit(second_file)) {
+        (Some(first), Some(second)) => (first, second),
+        _ => {return;},
+    };
+    if first.field != second.field {
+        println!("ERROR. The circuits are defined over different fields");
+        return;
+    }
+    let (witness, second_values) = match (read_input_witness(first_witness, &first.labels), read_input_witness(second_witness, &second.labels)) {
+        (Some(witness), Some(values)) => (witness, values),
+        _ => {
+            println!("ERROR. The witnesses could not be read");
+            return;
+        }
+    };
+    let constant = Constraint::<usize>::constant_coefficient();
+    // the signals of the second circuit go after the last label of the first one, both
+    // share the constant signal
+    let offset = first.signals.keys().max().cloned().unwrap_or(0);
+    let stem = |file : &str| std::path::Path::new(file).file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+    let (mut first_stem, mut second_stem) = (stem(first_file), stem(second_file));
+    if first_stem == second_stem {
+        first_stem.push_str(".1");
+        second_stem.push_str(".2");
+    }
+
+    let Circuit { mut constraints, mut forbidden, mut signals, labels, field, .. } = first;
+    let mut witness = witness;
+    for info in signals.values_mut() {
+        if info.role != SignalRole::Constant {
+            info.name = format!("{}.{}", first_stem, info.name);
+        }
+    }
+    for constraint in &second.constraints {
+        constraints.push_back(constraint.apply_offset(offset));
+    }
+    forbidden.extend(second.forbidden.iter().map(|signal| signal + offset));
+    for (signal, info) in second.signals {
+        if signal != constant {
+            signals.insert(signal + offset, SignalInfo { name: format!("{}.{}", second_stem, info.name), role: info.role });
+        }
+    }
+    for (signal, value) in second_values {
+        if signal != constant {
+            witness.insert(signal + offset, value);
+        }
+    }
+    let mut joint_labels: HashMap<String, usize> = signals.iter().map(|(signal, info)| (info.name.clone(), *signal)).collect();
+    joint_labels.insert("~one".to_string(), constant);
+
+    for (output, input) in &connections {
+        let (output_signal, input_signal) = match (labels.get(output), second.labels.get(input)) {
+            (Some(output_signal), Some(input_signal)) if *output_signal != constant && *input_signal != constant => (*output_signal, input_signal + offset),
+            _ => {
+                println!("ERROR. Unknown signals {} {}", output, input);
+                return;
+            }
+        };
+        if witness.get(&output_signal) != witness.get(&input_signal) {
+            println!("ERROR. The witnesses give different values to {} and {}", output, input);
+            return;
+        }
+        // output - input = 0, the input is no longer part of the interface of the composition
+        let mut expression = HashMap::new();
+        expression.insert(output_signal, BigInt::from(1));
+        expression.insert(input_signal, modular_arithmetic::prefix_sub(&BigInt::from(1), &field));
+        constraints.push_back(Constraint::new(HashMap::new(), HashMap::new(), expression));
+        forbidden.remove(&input_signal);
+        if let Some(info) = signals.get_mut(&input_signal) {
+            info.role = SignalRole::Private;
+        }
+    }
+    let config = match read_simplification_config(true, &flags, &joint_labels) {
+        Some(config) => config,
+        None => {return;},
+    };
+    let max_signal = offset + second.num_circuit_variables;
+    let signal_map = generate_storage_and_simplify(constraints, forbidden, max_signal, max_signal, field, &config, witness);
+    write_signal_files(&signals, &signal_map, ".");
+    println!("Señales conectadas: {}, wires de la composicion: {}", connections.len(), signal_map.len());
+}
+
+fn lint_circuit(filename : &str) {
+    let circuit = match read_circuit(filename) {
+        Some(circuit) => circuit,
+        None => {return;},
+    };
+    let Circuit { constraints, signals, field, .. } = circuit;
+    let storage = circuit_storage(constraints);
+    let warnings = lint::lint(&storage, &signals, &field);
+    let mut json = Vec::new();
+    for warning in &warnings {
+        let names: Vec<String> = warning.signals.iter().map(|s| signal_table::signal_name(&signals, *s)).collect();
+        println!("WARNING [{}] {} (constraints: {:?})", warning.kind.as_str(), warning.message, warning.constraints);
+        json.push(serde_json::json!({
+            "kind": warning.kind.as_str(),
+            "signals": names,
+            "constraints": warning.constraints,
+            "message": warning.message,
+        }));
+    }
+    println!("Total de avisos: {}", warnings.len());
+    let file = fs::File::create("lint.json").unwrap();
+    serde_json::to_writer_pretty(file, &json).unwrap();
+}
+
+// optimizer unique <file> [--input NAME]... [--input-prefix P]
+// writes uniqueness.json
+fn check_uniqueness(filename : &str, options : &[String]) {
+    let mut names = HashSet::new();
+    let mut prefixes = Vec::new();
+    let mut it = options.iter();
+    while let Some(option) = it.next() {
+        match (option.as_str(), it.next()) {
+            ("--input", Some(name)) => {names.insert(name.clone());},
+            ("--input-prefix", Some(prefix)) => prefixes.push(prefix.clone()),
+            _ => {
+                println!("ERROR. Unknown option {} or missing value", option);
+                return;
+            }
+        }
+    }
+    let circuit = match read_circuit(filename) {
+        Some(circuit) => circuit,
+        None => {return;},
+    };
+    let Circuit { constraints, signals, field, .. } = circuit;
+    let mut inputs = HashSet::new();
+    let mut checked = BTreeSet::new();
+    for (signal, info) in &signals {
+        if info.role == SignalRole::Constant {
+            continue;
+        }
+        if info.role == SignalRole::Input || names.contains(&info.name) || prefixes.iter().any(|p| info.name.starts_with(p.as_str())) {
+            inputs.insert(*signal);
+        } else {
+            checked.insert(*signal);
+        }
+    }
+    if inputs.is_empty() {
+        println!("ERROR. Expected the inputs with --input or --input-prefix");
+        return;
+    }
+    let storage = circuit_storage(constraints);
+    let report = uniqueness::check_uniqueness(&storage, &inputs, &checked, &field);
+    let mut not_unique_json = Vec::new();
+    for not_unique in &report.not_unique {
+        let name = signal_table::signal_name(&signals, not_unique.signal);
+        println!("NOT UNIQUE {} (constraints: {:?})", name, not_unique.constraints);
+        not_unique_json.push(serde_json::json!({
+            "signal": name,
+            "role": signal_table::signal_role(&signals, not_unique.signal).as_str(),
+            "constraints": not_unique.constraints,
+        }));
+    }
+    println!("Señales unicas: {}, señales no probadas unicas: {}", report.unique.len(), report.not_unique.len());
+    let unique_json: Vec<String> = report.unique.iter().map(|s| signal_table::signal_name(&signals, *s)).collect();
+    let json = serde_json::json!({
+        "inputs": inputs.len(),
+        "unique": unique_json,
+        "not_unique": not_unique_json,
+    });
+    let file = fs::File::create("uniqueness.json").unwrap();
+    serde_json::to_writer_pretty(file, &json).unwrap();
+}
+
+// optimizer specialize <file> <witness> <values> [flags]
+// the values file has the format of the witness, one NAME VALUE per line; writes
+// a.r1cs, witness.json, a.sym and roles.json
+fn specialize_circuit(filename : &str, witness_file : &String, values_file : &String, options : &[String]) {
+    let circuit = match read_circuit(filename) {
+        Some(circuit) => circuit,
+        None => {return;},
+    };
+    let Circuit { mut constraints, mut forbidden, mut signals, labels, field, num_circuit_variables } = circuit;
+    let witness = read_witness(witness_file, &labels);
+    let values = read_witness(values_file, &labels);
+    let config = match read_simplification_config(true, options, &labels) {
+        Some(config) => config,
+        None => {return;},
+    };
+    for (signal, value) in &values {
+        let name = signal_table::signal_name(&signals, *signal);
+        if *signal == Constraint::<usize>::constant_coefficient() {
+            println!("ERROR. The constant signal {} can not be specialized", name);
+            return;
+        }
+        let value = modular_arithmetic::add(value, &BigInt::from(0), &field);
+        if witness.get(signal) != Some(&value) {
+            println!("ERROR. The witness does not give the value {} to {}", value, name);
+            return;
+        }
+        // signal - value = 0, the linear simplification folds the constant into the
+        // constraints where the signal appears
+        let mut expression = HashMap::new();
+        expression.insert(*signal, BigInt::from(1));
+        if value != BigInt::from(0) {
+            expression.insert(Constraint::<usize>::constant_coefficient(), modular_arithmetic::prefix_sub(&value, &field));
+        }
+        constraints.push_back(Constraint::new(HashMap::new(), HashMap::new(), expression));
+        forbidden.remove(signal);
+        if let Some(info) = signals.get_mut(signal) {
+            info.role = SignalRole::Constant;
+        }
+    }
+    let signal_map = generate_storage_and_simplify(constraints, forbidden, num_circuit_variables, num_circuit_variables, field, &config, witness);
+    let kept = values.keys().filter(|signal| signal_map.contains_key(signal)).count();
+    if kept > 0 {
+        println!("WARNING. {} specialized signals could not be removed", kept);
+    }
+    write_signal_files(&signals, &signal_map, ".");
+    println!("Señales especializadas: {}, wires del circuito especializado: {}", values.len() - kept, signal_map.len());
+}
+
+// optimizer slice <file> --output NAME... [--input NAME]... [--input-prefix P] [--witness FILE]
+// writes the constraints of the cone of influence of the outputs in a.r1cs, a.sym,
+// roles.json and slice.json with the inputs the outputs depend on, and the witness of
+// the slice in witness.json when it is given
+fn slice_circuit(filename : &str, options : &[String]) {
+    let mut output_names = Vec::new();
+    let mut input_names = HashSet::new();
+    let mut prefixes = Vec::new();
+    let mut witness_file = None;
+    let mut it = options.iter();
+    while let Some(option) = it.next() {
+        match (option.as_str(), it.next()) {
+            ("--output", Some(name)) => output_names.push(name.clone()),
+            ("--witness", Some(file)) => witness_file = Some(file.clone()),
+            ("--input", Some(name)) => {input_names.insert(name.clone());},
+            ("--input-prefix", Some(prefix)) => prefixes.push(prefix.clone()),
+            _ => {
+                println!("ERROR. Unknown option {} or missing value", option);
+                return;
+            }
+        }
+    }
+    if output_names.is_empty() {
+        println!("ERROR. Expected the outputs of the slice with --output");
+        return;
+    }
+    let circuit = match read_circuit(filename) {
+        Some(circuit) => circuit,
+        None => {return;},
+    };
+    let Circuit { constraints, signals, labels, field, num_circuit_variables, .. } = circuit;
+    let mut outputs = BTreeSet::new();
+    for name in &output_names {
+        match labels.get(name) {
+            Some(signal) if *signal != Constraint::<usize>::constant_coefficient() => {outputs.insert(*signal);},
+            _ => {
+                println!("ERROR. Unknown signal {}", name);
+                return;
+            }
+        }
+    }
+    let mut inputs = HashSet::new();
+    let mut boundary = HashSet::new();
+    for (signal, info) in &signals {
+        if info.role == SignalRole::Input || input_names.contains(&info.name) || prefixes.iter().any(|p| info.name.starts_with(p.as_str())) {
+            inputs.insert(*signal);
+            boundary.insert(*signal);
+        } else if info.role == SignalRole::Output && !outputs.contains(signal) {
+            boundary.insert(*signal);
+        }
+    }
+    let storage = circuit_storage(constraints);
+    let slice = slicing::cone_of_influence(&storage, &outputs, &boundary);
+
+    let mut sliced = circom_algebra::constraint_storage::ConstraintStorage::new();
+    for c_id in &slice.constraints {
+        sliced.add_constraint(storage.read_constraint(*c_id).unwrap());
+    }
+    let constant = Constraint::<usize>::constant_coefficient();
+    let mut signal_map = HashMap::new();
+    signal_map.insert(constant, 0);
+    for signal in &slice.signals {
+        signal_map.insert(*signal, signal_map.len());
+    }
+    let list = constraint_list::r1cs_porting::ConstraintList {
+        field,
+        constraints: sliced,
+        no_public_inputs: 0,
+        no_public_outputs: 0,
+        no_private_inputs: 0,
+        no_labels: num_circuit_variables,
+        signal_map: signal_map.clone(),
+    };
+    if constraint_list::r1cs_porting::port_r1cs(&list, "a.r1cs").is_err() {
+        println!("ERROR. The R1CS file could not be written");
+        return;
+    }
+    write_signal_files(&signals, &signal_map, ".");
+    if let Some(file) = witness_file {
+        let mut witness = read_witness(&file, &labels);
+        // the signals of the slice keep their order
+        witness.retain(|signal, _| signal_map.contains_key(signal));
+        write_witness(witness);
+    }
+
+    let depends: Vec<String> = slice.boundary.iter()
+        .filter(|signal| inputs.contains(signal))
+        .map(|signal| signal_table::signal_name(&signals, *signal))
+        .collect();
+    println!("Entradas de las que dependen las salidas: {:?}", depends);
+    println!("Constraints del corte: {}, señales del corte: {}", slice.constraints.len(), slice.signals.len());
+    let json = serde_json::json!({
+        "outputs": output_names,
+        "inputs": depends,
+        "constraints": slice.constraints,
+    });
+    let file = fs::File::create("slice.json").unwrap();
+    serde_json::to_writer_pretty(file, &json).unwrap();
+}
+
+// writes a.sym (label, wire, 0, name; wire -1 for the removed signals) and roles.json
+// in dir
+fn write_signal_files(signals : &SignalTable, signal_map : &HashMap<usize, usize>, dir : &str) {
+    let mut sym = match SymFile::new(&format!("{}/a.sym", dir)) {
+        Ok(sym) => sym,
+        Err(()) => {
+            println!("ERROR. The sym file could not be written");
+            return;
+        }
+    };
+    let mut labels: Vec<&usize> = signals.keys().collect();
+    labels.sort();
+    let mut roles = Vec::new();
+    for label in labels {
+        let info = &signals[label];
+        let wire = signal_map.get(label).map_or(-1, |wire| *wire as i64);
+        let elem = SymElem { original: *label as i64, witness: wire, node_id: 0, symbol: info.name.clone() };
+        if SymFile::write_sym_elem(&mut sym, elem).is_err() {
+            println!("ERROR. The sym file could not be written");
+            return;
+        }
+        roles.push(serde_json::json!({
+            "signal": info.name,
+            "role": info.role.as_str(),
+            "wire": signal_map.get(label),
+        }));
+    }
+    SymFile::close(sym);
+    let file = fs::File::create(format!("{}/roles.json", dir)).unwrap();
+    serde_json::to_writer_pretty(file, &roles).unwrap();
+}
+
+// optimizer graph <file> <output> [--clusters linear|monomial|nonlinear] [--cluster N] [--prefix P]
+// writes <output>.dot and <output>.graphml
+fn export_graph(filename : &str, output : &str, options : &[String]) {
+    let mut kind = None;
+    let mut filter = graph_export::GraphFilter::default();
+    let mut it = options.iter();
+    while let Some(option) = it.next() {
+        match option.as_str() {
+            "--clusters" => {
+                kind = match it.next().map(|v| v.as_str()) {
+                    Some("linear") => Some(graph_export::ClusterKind::Linear),
+                    Some("monomial") => Some(graph_export::ClusterKind::Monomial),
+                    Some("nonlinear") => Some(graph_export::ClusterKind::NonLinear),
+                    _ => {
+                        println!("ERROR. Expected linear, monomial or nonlinear after --clusters");
+                        return;
+                    }
+                }
+            }
+            "--cluster" => match read_option_value(option, it.next()) {
+                Some(cluster) => filter.cluster = Some(cluster),
+                None => {return;},
+            },
+            "--prefix" => match it.next() {
+                Some(prefix) => filter.signal_prefix = Some(prefix.clone()),
+                None => {
+                    println!("ERROR. Expected a prefix after --prefix");
+                    return;
+                }
+            },
+            _ => {
+                println!("ERROR. Unknown option {}", option);
+                return;
+            }
+        }
+    }
+    if filter.cluster.is_some() && kind.is_none() {
+        println!("ERROR. Expected --clusters with --cluster");
+        return;
+    }
+    let circuit = match read_circuit(filename) {
+        Some(circuit) => circuit,
+        None => {return;},
+    };
+    let Circuit { constraints, signals, field, .. } = circuit;
+    let storage = circuit_storage(constraints);
+    let graph = graph_export::build_graph(&storage, &signals, &field, kind, &filter);
+    let dot = format!("{}.dot", output);
+    let graphml = format!("{}.graphml", output);
+    if graph.write_dot(&dot).is_err() || graph.write_graphml(&graphml).is_err() {
+        println!("ERROR. The graph could not be written");
+        return;
+    }
+    println!("Grafo con {} nodos y {} aristas escrito en {} y {}", graph.nodes.len(), graph.edges.len(), dot, graphml);
+}
+
+// writes cluster_stats.json
+fn write_cluster_stats(stats : &cluster_stats::ClusterStats, slowest : usize) {
+    let histograms: Vec<serde_json::Value> = stats.histograms().iter().map(|histogram| {
+        let sizes: serde_json::Map<String, serde_json::Value> = histogram.sizes.iter()
+            .map(|(size, count)| (size.to_string(), serde_json::json!(count)))
+            .collect();
+        serde_json::json!({
+            "round": histogram.round,
+            "phase": histogram.phase.as_str(),
+            "sizes": sizes,
+        })
+    }).collect();
+    let cluster_json = |cluster : &cluster_stats::ClusterTiming| serde_json::json!({
+        "round": cluster.round,
+        "phase": cluster.phase.as_str(),
+        "constraints": cluster.constraints,
+        "monomials": cluster.monomials,
+        "wall_time_ms": cluster.time.as_secs_f64() * 1000.0,
+    });
+    let slowest: Vec<serde_json::Value> = stats.slowest(slowest).into_iter().map(cluster_json).collect();
+    let non_linear: Vec<serde_json::Value> = stats.clusters.iter()
+        .filter(|cluster| cluster.monomials.is_some())
+        .map(cluster_json)
+        .collect();
+    let json = serde_json::json!({
+        "rounds": stats.no_rounds(),
+        "clusters": stats.clusters.len(),
+        "zero_constraints_removed": stats.zero_constraints_removed,
+        "histograms": histograms,
+        "slowest": slowest,
+        "non_linear_clusters": non_linear,
+    });
+    let file = fs::File::create("cluster_stats.json").unwrap();
+    serde_json::to_writer_pretty(file, &json).unwrap();
+}
+
+fn expression_to_json(expression : &HashMap<usize, BigInt>) -> serde_json::Value {
+    let mut labels: Vec<&usize> = expression.keys().collect();
+    labels.sort();
+    let map: serde_json::Map<String, serde_json::Value> = labels.into_iter()
+        .map(|label| (label.to_string(), serde_json::json!(expression[label].to_string())))
+        .collect();
+    serde_json::Value::Object(map)
+}
+
+fn expression_from_json(value : &serde_json::Value) -> Option<HashMap<usize, BigInt>> {
+    let mut expression = HashMap::new();
+    for (label, coefficient) in value.as_object()? {
+        expression.insert(label.parse().ok()?, coefficient.as_str()?.parse().ok()?);
+    }
+    Some(expression)
+}
+
+fn read_json(file : &str) -> Option<serde_json::Value> {
+    let contents = match fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(_) => {
+            println!("ERROR. File {} not found", file);
+            return None;
+        }
+    };
+    match serde_json::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            println!("ERROR. File {} is not valid JSON", file);
+            None
+        }
+    }
+}
+
+// writes substitutions.json, signal_map.json and constraints.json in dir, the
+// constraints use the labels of the original circuit
+fn write_optimization_artifacts(log : &[Substitution<usize>], signal_map : &HashMap<usize, usize>,
+                                storage : &circom_algebra::constraint_storage::ConstraintStorage, dir : &str) {
+    let substitutions: Vec<serde_json::Value> = log.iter().map(|substitution| serde_json::json!({
+        "signal": substitution.from(),
+        "expression": expression_to_json(substitution.to()),
+    })).collect();
+    let file = fs::File::create(format!("{}/substitutions.json", dir)).unwrap();
+    serde_json::to_writer_pretty(file, &substitutions).unwrap();
+
+    let map: BTreeMap<String, usize> = signal_map.iter().map(|(from, to)| (from.to_string(), *to)).collect();
+    let file = fs::File::create(format!("{}/signal_map.json", dir)).unwrap();
+    serde_json::to_writer_pretty(file, &map).unwrap();
+
+    let mut constraints = Vec::new();
+    for c_id in storage.get_ids() {
+        let constraint = storage.read_constraint(c_id).unwrap();
+        if !constraint.is_empty() {
+            constraints.push(serde_json::json!([
+                expression_to_json(constraint.a()),
+                expression_to_json(constraint.b()),
+                expression_to_json(constraint.c()),
+            ]));
+        }
+    }
+    let file = fs::File::create(format!("{}/constraints.json", dir)).unwrap();
+    serde_json::to_writer_pretty(file, &serde_json::json!({ "constraints": constraints })).unwrap();
+}
+
+struct OptimizationArtifacts {
+    log: Vec<Substitution<usize>>,
+    signal_map: HashMap<usize, usize>,
+    constraints: circom_algebra::constraint_storage::ConstraintStorage,
+}
+
+fn read_optimization_artifacts(dir : &str) -> Option<OptimizationArtifacts> {
+    let mut log = Vec::new();
+    for substitution in read_json(&format!("{}/substitutions.json", dir))?.as_array()? {
+        let from = substitution["signal"].as_u64()? as usize;
+        let coefficients = expression_from_json(&substitution["expression"])?;
+        log.push(Substitution::new(from, ArithmeticExpression::Linear { coefficients })?);
+    }
+    let mut signal_map = HashMap::new();
+    for (from, to) in read_json(&format!("{}/signal_map.json", dir))?.as_object()? {
+        signal_map.insert(from.parse().ok()?, to.as_u64()? as usize);
+    }
+    let mut constraints = LinkedList::new();
+    for constraint in read_json(&format!("{}/constraints.json", dir))?["constraints"].as_array()? {
+        let a = expression_from_json(&constraint[0])?;
+        let b = expression_from_json(&constraint[1])?;
+        let c = expression_from_json(&constraint[2])?;
+        constraints.push_back(Constraint::new(a, b, c));
+    }
+    Some(OptimizationArtifacts { log, signal_map, constraints: circuit_storage(constraints) })
+}
+
+// optimizer diff <file> [<dir>]
+// compares the circuit with the artifacts written by --substitution-log in dir,
+// writes diff.json
+fn diff_circuit(filename : &str, dir : &str) {
+    let circuit = match read_circuit(filename) {
+        Some(circuit) => circuit,
+        None => {return;},
+    };
+    let OptimizationArtifacts { log, signal_map, constraints: optimized } = match read_optimization_artifacts(dir) {
+        Some(artifacts) => artifacts,
+        None => {
+            println!("ERROR. The artifacts of the optimization could not be read from {}", dir);
+            return;
+        }
+    };
+    let Circuit { constraints, signals, field, .. } = circuit;
+    let original = circuit_storage(constraints);
+    let diff = system_diff::diff(&original, &optimized, &log, &signal_map, &field);
+    let name = |signal : usize| signal_table::signal_name(&signals, signal);
+    let show = |storage : &circom_algebra::constraint_storage::ConstraintStorage, c_id : usize|
+        system_diff::constraint_to_string(&storage.read_constraint(c_id).unwrap(), &signals, &field);
+
+    println!("Señales eliminadas: {}", diff.removed_signals.len());
+    let mut removed_json = Vec::new();
+    for removed in &diff.removed_signals {
+        let replacement = removed.replacement.as_ref()
+            .map(|expression| system_diff::linear_expression_to_string(expression, &signals, &field));
+        match &replacement {
+            Some(replacement) => println!("  {} -> {}", name(removed.signal), replacement),
+            None => println!("  {} (without substitution)", name(removed.signal)),
+        }
+        removed_json.push(serde_json::json!({
+            "signal": name(removed.signal),
+            "label": removed.signal,
+            "replacement": replacement,
+        }));
+    }
+    println!("Señales renumeradas: {}", diff.renamed_signals.len());
+    let mut renamed_json = Vec::new();
+    for (from, to) in &diff.renamed_signals {
+        println!("  {}: {} -> {}", name(*from), from, to);
+        renamed_json.push(serde_json::json!({ "signal": name(*from), "from": from, "to": to }));
+    }
+    println!("Constraints eliminadas: {}", diff.removed_constraints.len());
+    let mut removed_constraints_json = Vec::new();
+    for c_id in &diff.removed_constraints {
+        println!("  c{}: {}", c_id, show(&original, *c_id));
+        removed_constraints_json.push(serde_json::json!({ "constraint": c_id, "original": show(&original, *c_id) }));
+    }
+    println!("Constraints reescritas: {}", diff.rewritten_constraints.len());
+    let mut rewritten_json = Vec::new();
+    for rewritten in &diff.rewritten_constraints {
+        let shape = if rewritten.changed_shape { " (changed shape)" } else { "" };
+        println!("  c{}{}: {}", rewritten.original, shape, show(&original, rewritten.original));
+        println!("    -> {}", show(&optimized, rewritten.optimized));
+        rewritten_json.push(serde_json::json!({
+            "constraint": rewritten.original,
+            "changed_shape": rewritten.changed_shape,
+            "original": show(&original, rewritten.original),
+            "optimized": show(&optimized, rewritten.optimized),
+        }));
+    }
+    println!("Constraints deducidas: {}", diff.deduced_constraints.len());
+    let mut deduced_json = Vec::new();
+    for c_id in &diff.deduced_constraints {
+        println!("  {}", show(&optimized, *c_id));
+        deduced_json.push(serde_json::json!(show(&optimized, *c_id)));
+    }
+    println!("Constraints sin cambios: {}", diff.unchanged_constraints.len());
+    let json = serde_json::json!({
+        "removed_signals": removed_json,
+        "renamed_signals": renamed_json,
+        "removed_constraints": removed_constraints_json,
+        "rewritten_constraints": rewritten_json,
+        "deduced_constraints": deduced_json,
+        "unchanged_constraints": diff.unchanged_constraints.len(),
+    });
+    let file = fs::File::create("diff.json").unwrap();
+    serde_json::to_writer_pretty(file, &json).unwrap();
+}
+
+fn circuit_storage(constraints: LinkedList<Constraint<usize>>) -> circom_algebra::constraint_storage::ConstraintStorage {
+    let mut storage = circom_algebra::constraint_storage::ConstraintStorage::new();
+    for constraint in constraints {
+        storage.add_constraint(constraint);
+    }
+    storage
+}
+
+fn read_simplification_config(apply_non_linear : bool, options : &[String], labels : &HashMap<String, usize>) -> Option<constraint_simplification::SimplificationConfig> {
+    let mut config = constraint_simplification::SimplificationConfig {
+        apply_non_linear,
+        gaussian_elimination: false,
+        groebner_cluster_size: 0,
+        groebner_degree: 3,
+        groebner_time_budget_ms: 100,
+        groebner_pair_budget: 1000,
+        merge_duplicate_products: false,
+        boolean_detection: false,
+        bit_decompositions: false,
+        dead_definitions: false,
+        cluster_stats: false,
+        slowest_clusters: 10,
+        substitution_log: false,
+        fast_equalities: false,
+        deterministic: true,
+        incremental_non_linear: true,
+        cluster_cache: false,
+        speculative_elimination: false,
+        constraint_weight: 10,
+        common_subexpressions: false,
+        fold_single_use_products: false,
+        degenerate_quadratics: false,
+        non_zero_cancellation: false,
+        range_analysis: false,
+        witness_equalities: false,
+        extra_witnesses: Vec::new(),
+    };
+    let mut it = options.iter();
+    while let Some(option) = it.next() {
+        match option.as_str() {
+            "--gaussian" => config.gaussian_elimination = true,
+            "--groebner" => config.groebner_cluster_size = read_option_value(option, it.next())?,
+            "--groebner-degree" => config.groebner_degree = read_option_value(option, it.next())?,
+            "--groebner-budget" => config.groebner_time_budget_ms = read_option_value(option, it.next())?,
+            "--groebner-pairs" => config.groebner_pair_budget = read_option_value(option, it.next())?,
+            "--merge-products" => config.merge_duplicate_products = true,
+            "--booleans" => config.boolean_detection = true,
+            "--bit-decompositions" => config.bit_decompositions = true,
+            "--dead-definitions" => config.dead_definitions = true,
+            "--stats" => config.cluster_stats = true,
+            "--stats-slowest" => config.slowest_clusters = read_option_value(option, it.next())?,
+            "--substitution-log" => config.substitution_log = true,
+            "--fast-equalities" => config.fast_equalities = true,
+            "--nondeterministic" => config.deterministic = false,
+            "--full-reclustering" => config.incremental_non_linear = false,
+            "--cluster-cache" => config.cluster_cache = true,
+            "--speculative" => config.speculative_elimination = true,
+            "--constraint-weight" => config.constraint_weight = read_option_value(option, it.next())?,
+            "--cse" => config.common_subexpressions = true,
+            "--fold-products" => config.fold_single_use_products = true,
+            "--degenerate-quadratics" => config.degenerate_quadratics = true,
+            "--non-zero" => config.non_zero_cancellation = true,
+            "--ranges" => config.range_analysis = true,
+            "--witness-equalities" => config.witness_equalities = true,
+            "--extra-witness" => match it.next() {
+                Some(file) => config.extra_witnesses.push(read_witness(file, labels)),
+                None => {
+                    println!("ERROR. Expected a witness file after --extra-witness");
+                    return None;
+                }
+            },
+            _ => {
+                println!("ERROR. Unknown option {}", option);
+                return None;
+            }
+        }
+    }
+    Some(config)
+}
+
+fn read_option_value<T : std::str::FromStr>(option : &str, value : Option<&String>) -> Option<T> {
+    match value.and_then(|v| v.parse().ok()) {
+        Some(value) => Some(value),
+        None => {
+            println!("ERROR. Expected a number after {}", option);
+            None
+        }
+    }
+}
+
 fn read_constraint (line : &str, prime_field : &BigInt, max : & HashMap<&str,usize>) -> Constraint<usize> {// Constraint<usize> {
     let (equual,a_b_mod, c_mod) = break_in_three(line);
     //println!("Constraint: {}", line);
@@ -350,8 +1211,8 @@ pub struct ConstraintList {
     pub signal_map: SignalMap,
 }
 
-pub fn generate_storage_and_simplify(constraints: LinkedList<Constraint<usize>>, forb: HashSet<usize>, no_labels: usize, max_signal: usize,  field: BigInt, apply_simp: bool,
-                                      witness: BTreeMap<usize, BigInt>){
+pub fn generate_storage_and_simplify(constraints: LinkedList<Constraint<usize>>, forb: HashSet<usize>, no_labels: usize, max_signal: usize,  field: BigInt, config: &constraint_simplification::SimplificationConfig,
+                                      witness: BTreeMap<usize, BigInt>) -> HashMap<usize, usize> {
     let mut linear = LinkedList::new();
     let mut storage = circom_algebra::constraint_storage::ConstraintStorage::new();
     for constraint in constraints{
@@ -361,7 +1222,15 @@ pub fn generate_storage_and_simplify(constraints: LinkedList<Constraint<usize>>,
             storage.add_constraint(constraint);
         }
     }
-    let (signalmap,witness) = constraint_list::constraint_simplification::simplification(linear, & mut storage, forb, no_labels, max_signal,  field.clone(), apply_simp, witness);
+    let (signalmap,witness,stats,log) = constraint_list::constraint_simplification::simplification(linear, & mut storage, forb, no_labels, max_signal,  field.clone(), config, witness);
+    if let Some(stats) = stats {
+        write_cluster_stats(&stats, config.slowest_clusters);
+    }
+    if let Some(log) = log {
+        write_optimization_artifacts(&log, &signalmap, &storage, ".");
+    }
+    // the signals added by the simplification get labels after the ones of the circuit
+    let no_labels = signalmap.keys().max().map_or(no_labels, |max| std::cmp::max(no_labels, max + 1));
     let cl = constraint_list::r1cs_porting::ConstraintList{
         field : field,
         constraints : storage,
@@ -369,8 +1238,11 @@ pub fn generate_storage_and_simplify(constraints: LinkedList<Constraint<usize>>,
         no_public_outputs : 0,
         no_private_inputs : 0,
         no_labels : no_labels,
-        signal_map : signalmap
+        signal_map : signalmap.clone()
     };   
     write_witness(witness);
-    constraint_list::r1cs_porting::port_r1cs(&cl,"a.r1cs");
+    if constraint_list::r1cs_porting::port_r1cs(&cl,"a.r1cs").is_err() {
+        println!("ERROR. The R1CS file could not be written");
+    }
+    signalmap
 }
\ No newline at end of file
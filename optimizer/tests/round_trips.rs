@@ -0,0 +1,205 @@
+use circom_algebra::modular_arithmetic;
+use circom_algebra::num_bigint::BigInt;
+use constraint_writers::r1cs_reader;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// x*y = z, out0 = z + w, x*x = out1
+const CIRCUIT: &str = "; Auto generated by ZoKrates
+; Number of circuit variables: 8
+; Number of equalities: 3
+
+(declare-const |~prime| Int)
+(declare-const |~one| Int)
+(declare-const |_0| Int)
+(declare-const |_1| Int)
+(declare-const |_2| Int)
+(declare-const |_3| Int)
+(declare-const |~out_0| Int)
+(declare-const |~out_1| Int)
+(assert (and
+(= ~prime 18446744069414584321)
+(= |~one| 1)
+(= (mod (* (+ (* |_0| 1)) (+ (* |_1| 1))) ~prime) (mod (+ (* |_2| 1)) ~prime))
+(= (mod (* (+ ) (+ )) ~prime) (mod (+ (* |~out_0| 1) (* |_2| 18446744069414584320) (* |_3| 18446744069414584320)) ~prime))
+(= (mod (* (+ (* |_0| 1)) (+ (* |_0| 1))) ~prime) (mod (+ (* |~out_1| 1)) ~prime))
+))
+";
+
+const WITNESS: &str = "~one 1
+_0 2
+_1 3
+_2 6
+_3 5
+~out_0 11
+~out_1 4
+";
+
+// x*x = y, out = y + 1
+const SQUARE: &str = "; Auto generated by ZoKrates
+; Number of circuit variables: 5
+; Number of equalities: 2
+
+(declare-const |~prime| Int)
+(declare-const |~one| Int)
+(declare-const |_0| Int)
+(declare-const |_1| Int)
+(declare-const |~out_0| Int)
+(assert (and
+(= ~prime 18446744069414584321)
+(= |~one| 1)
+(= (mod (* (+ (* |_0| 1)) (+ (* |_0| 1))) ~prime) (mod (+ (* |_1| 1)) ~prime))
+(= (mod (* (+ ) (+ )) ~prime) (mod (+ (* |~out_0| 1) (* |_1| 18446744069414584320) (* |~one| 18446744069414584320)) ~prime))
+))
+";
+
+const SQUARE_WITNESS: &str = "~one 1
+_0 4
+_1 16
+~out_0 17
+";
+
+// a fresh directory for the files written by the optimizer
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("optimizer-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("c.smt2"), CIRCUIT).unwrap();
+    fs::write(dir.join("c.smt2.wit"), WITNESS).unwrap();
+    fs::write(dir.join("sq.smt2"), SQUARE).unwrap();
+    fs::write(dir.join("sq.smt2.wit"), SQUARE_WITNESS).unwrap();
+    dir
+}
+
+fn run(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_optimizer")).args(args).current_dir(dir).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+// a.r1cs is satisfied by witness.json, returns the number of constraints
+fn check_witness(dir: &Path) -> usize {
+    let data = r1cs_reader::read_r1cs(dir.join("a.r1cs").to_str().unwrap()).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("witness.json")).unwrap()).unwrap();
+    let witness: Vec<BigInt> = json.as_array().unwrap().iter().map(|value| value.as_str().unwrap().parse().unwrap()).collect();
+    // the ZoKrates reader counts a wire more than the signals it declares, which no
+    // constraint uses
+    assert!(witness.len() <= data.total_wires);
+    let field = &data.field;
+    let evaluate = |expression: &HashMap<usize, BigInt>| {
+        expression.iter().fold(BigInt::from(0), |value, (wire, coefficient)| {
+            modular_arithmetic::add(&value, &modular_arithmetic::mul(coefficient, &witness[*wire], field), field)
+        })
+    };
+    for (a, b, c) in &data.constraints {
+        assert_eq!(modular_arithmetic::mul(&evaluate(a), &evaluate(b), field), evaluate(c));
+    }
+    data.constraints.len()
+}
+
+// the wire of each name in a.sym, -1 for the removed signals
+fn sym_wires(dir: &Path) -> HashMap<String, i64> {
+    let mut wires = HashMap::new();
+    for line in fs::read_to_string(dir.join("a.sym")).unwrap().lines() {
+        let fields: Vec<&str> = line.splitn(4, ',').collect();
+        wires.insert(fields[3].to_string(), fields[1].parse().unwrap());
+    }
+    wires
+}
+
+#[test]
+fn specialized_circuit_keeps_its_witness() {
+    let dir = scratch("specialize");
+    fs::write(dir.join("values.txt"), "_1 3\n").unwrap();
+    run(&dir, &["specialize", "c.smt2", "c.smt2.wit", "values.txt"]);
+    // x*3 = z and out0 = z + w only define z and w, x*x = out1 is left
+    assert_eq!(check_witness(&dir), 1);
+    let wires = sym_wires(&dir);
+    assert_eq!(wires["_1"], -1);
+    assert!(wires["~out_0"] > 0 && wires["~out_1"] > 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn specialized_value_must_match_the_witness() {
+    let dir = scratch("specialize-mismatch");
+    fs::write(dir.join("values.txt"), "_1 4\n").unwrap();
+    let output = run(&dir, &["specialize", "c.smt2", "c.smt2.wit", "values.txt"]);
+    assert!(output.contains("The witness does not give the value 4 to _1"));
+    assert!(!dir.join("a.r1cs").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn slice_keeps_the_cone_of_its_output() {
+    let dir = scratch("slice");
+    run(&dir, &["slice", "c.smt2", "--output", "~out_0", "--input", "_0", "--input", "_1", "--witness", "c.smt2.wit"]);
+    assert_eq!(check_witness(&dir), 2);
+    let wires = sym_wires(&dir);
+    assert_eq!(wires["~out_1"], -1);
+    assert!(wires["_3"] > 0);
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("slice.json")).unwrap()).unwrap();
+    assert_eq!(json["inputs"], serde_json::json!(["_0", "_1"]));
+    assert_eq!(json["constraints"], serde_json::json!([0, 1]));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn slice_stops_at_the_inputs() {
+    let dir = scratch("slice-inputs");
+    run(&dir, &["slice", "c.smt2", "--output", "~out_1", "--input", "_0", "--witness", "c.smt2.wit"]);
+    assert_eq!(check_witness(&dir), 1);
+    let wires = sym_wires(&dir);
+    assert_eq!(wires["_1"], -1);
+    assert_eq!(wires["~out_0"], -1);
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("slice.json")).unwrap()).unwrap();
+    assert_eq!(json["inputs"], serde_json::json!(["_0"]));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn linked_circuits_share_the_connected_signals() {
+    let dir = scratch("link");
+    let output = run(&dir, &["link", "c.smt2", "c.smt2.wit", "sq.smt2", "sq.smt2.wit", "--connect", "~out_1", "_0"]);
+    assert!(output.contains("Señales conectadas: 1"));
+    check_witness(&dir);
+    let wires = sym_wires(&dir);
+    assert!(wires["c.~out_0"] > 0 && wires["sq.~out_0"] > 0);
+    assert!(wires.contains_key("sq._0"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sliced_r1cs_is_linked_with_a_circuit() {
+    let dir = scratch("link-r1cs");
+    let cone = dir.join("cone");
+    fs::create_dir_all(&cone).unwrap();
+    run(&cone, &["slice", "../c.smt2", "--output", "~out_1", "--input", "_0", "--witness", "../c.smt2.wit"]);
+    let output = run(&dir, &["link", "cone/a.r1cs", "cone/witness.json", "sq.smt2", "sq.smt2.wit", "--connect", "~out_1", "_0"]);
+    assert!(output.contains("Señales conectadas: 1"));
+    check_witness(&dir);
+    assert!(sym_wires(&dir).contains_key("a.~out_1"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn connected_signals_must_have_the_same_value() {
+    let dir = scratch("link-mismatch");
+    let output = run(&dir, &["link", "c.smt2", "c.smt2.wit", "sq.smt2", "sq.smt2.wit", "--connect", "~out_0", "_0"]);
+    assert!(output.contains("The witnesses give different values to ~out_0 and _0"));
+    assert!(!dir.join("a.r1cs").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn graph_cluster_needs_a_kind_of_clusters() {
+    let dir = scratch("graph-cluster");
+    let output = run(&dir, &["graph", "c.smt2", "g", "--cluster", "0"]);
+    assert!(output.contains("ERROR. Expected --clusters with --cluster"));
+    assert!(!dir.join("g.dot").exists());
+    run(&dir, &["graph", "c.smt2", "g", "--clusters", "linear", "--cluster", "0"]);
+    assert!(dir.join("g.dot").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}